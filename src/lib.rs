@@ -6,13 +6,16 @@
 
 #[cfg(test)]
 mod mock;
+pub mod randomness;
 mod types;
 
 use efinity_contracts::{prelude::*, Freeze, FreezeType};
 use ink::codegen::Env;
+use ink_env::hash::Blake2x256;
 use ink_lang as ink;
+use ink_prelude::vec::Vec;
 use ink_storage::{traits::SpreadAllocate, Mapping};
-use scale::{Decode, Encode};
+use randomness::SecretRandomness;
 use types::{Error, Result, *};
 
 /// The attribute key used for equipment
@@ -20,12 +23,127 @@ fn attribute_key() -> AttributeKey {
     b"equipment".to_vec()
 }
 
+/// The number of config versions kept in the history, including the current one
+const CONFIG_HISTORY_SIZE: u32 = 10;
+
+/// The maximum number of templates in the bestiary, because they are all loaded to pick an enemy
+const MAX_ENEMY_TEMPLATES: usize = 32;
+
+/// The maximum number of pending scheduled config mutations, because they are checked by every gameplay message
+const MAX_CONFIG_SCHEDULES: usize = 16;
+
 /// Multi-Tokens example smart contract
 #[ink::contract(env = EfinityEnvironment)]
 #[allow(clippy::extra_unused_lifetimes, clippy::let_unit_value)]
 mod game {
     use super::*;
 
+    /// A new owner was proposed
+    #[ink(event)]
+    pub struct OwnershipProposed {
+        /// The current owner
+        pub owner: AccountId,
+        /// The account that must accept ownership
+        pub pending_owner: AccountId,
+    }
+
+    /// The proposed owner accepted ownership
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        /// The previous owner
+        pub previous_owner: AccountId,
+        /// The new owner
+        pub new_owner: AccountId,
+    }
+
+    /// The owner cancelled the proposal of a new owner
+    #[ink(event)]
+    pub struct OwnershipProposalCancelled {
+        /// The account that was proposed
+        pub pending_owner: AccountId,
+    }
+
+    /// A role was granted to an account
+    #[ink(event)]
+    pub struct RoleGranted {
+        /// The account that received the role
+        pub account_id: AccountId,
+        /// The role that was granted
+        pub role: Role,
+        /// The account that granted the role
+        pub sender: AccountId,
+    }
+
+    /// A role was revoked from an account
+    #[ink(event)]
+    pub struct RoleRevoked {
+        /// The account that lost the role
+        pub account_id: AccountId,
+        /// The role that was revoked
+        pub role: Role,
+        /// The account that revoked the role. Same as `account_id` if the role was renounced.
+        pub sender: AccountId,
+    }
+
+    /// A feature of the game was paused
+    #[ink(event)]
+    pub struct Paused {
+        /// The feature that was paused
+        pub feature: Feature,
+        /// The account that paused it
+        pub sender: AccountId,
+    }
+
+    /// A feature of the game was unpaused
+    #[ink(event)]
+    pub struct Unpaused {
+        /// The feature that was unpaused
+        pub feature: Feature,
+        /// The account that unpaused it
+        pub sender: AccountId,
+    }
+
+    /// The config was changed
+    #[ink(event)]
+    pub struct ConfigUpdated {
+        /// The version of the new config
+        pub version: u32,
+        /// The fields that changed, with their old and new values
+        pub changes: Vec<ConfigChange>,
+        /// The account that changed the config. `None` if a scheduled mutation was applied or reverted by
+        /// the game.
+        pub sender: Option<AccountId>,
+        /// The id of the schedule whose mutation was applied or reverted, if there is one
+        pub schedule_id: Option<ConfigScheduleId>,
+    }
+
+    /// A config mutation was scheduled
+    #[ink(event)]
+    pub struct ConfigMutationScheduled {
+        /// The id of the schedule
+        pub schedule_id: ConfigScheduleId,
+        /// The block number the mutation is applied at
+        pub activate_at_block: BlockNumber,
+        /// The block number the mutation is reverted at
+        pub revert_at_block: Option<BlockNumber>,
+    }
+
+    /// A scheduled config mutation was cancelled
+    #[ink(event)]
+    pub struct ConfigScheduleCancelled {
+        /// The id of the schedule
+        pub schedule_id: ConfigScheduleId,
+    }
+
+    /// A scheduled config mutation could not be applied or reverted because the config would be invalid
+    #[ink(event)]
+    pub struct ConfigScheduleFailed {
+        /// The id of the schedule
+        pub schedule_id: ConfigScheduleId,
+        /// The reason the config was invalid
+        pub error: Error,
+    }
+
     /// A hero was created
     #[ink(event)]
     pub struct HeroCreated {
@@ -44,6 +162,10 @@ mod game {
         pub hero_id: AccountId,
         /// The enemy generated for this battle
         pub enemy: Enemy,
+        /// The id of the template the enemy was generated from
+        pub template_id: Option<EnemyTypeId>,
+        /// True if the enemy is a boss
+        pub is_boss: bool,
     }
 
     /// The battle was advanced by a round
@@ -68,6 +190,115 @@ mod game {
         pub hero_wins: bool,
         /// The total number of rounds the battle took
         pub round_count: u32,
+        /// True if the enemy was a boss
+        pub is_boss: bool,
+    }
+
+    /// A hero escaped from a battle
+    #[ink(event)]
+    pub struct BattleFled {
+        /// The `AccountId` of the hero
+        pub hero_id: AccountId,
+        /// The total number of rounds the battle took
+        pub round_count: u32,
+        /// The amount of gold taken from the hero
+        pub gold_penalty: TokenBalance,
+        /// The amount of health taken from the hero
+        pub health_penalty: u32,
+    }
+
+    /// A hero reached a new level
+    #[ink(event)]
+    pub struct HeroLeveledUp {
+        /// The `AccountId` of the hero
+        pub hero_id: AccountId,
+        /// The new level of the hero
+        pub level: u32,
+        /// The new max health of the hero
+        pub max_health: u32,
+    }
+
+    /// A hero challenged another hero to a duel
+    #[ink(event)]
+    pub struct DuelChallenged {
+        /// The id of the duel
+        pub duel_id: DuelId,
+        /// The `AccountId` of the hero that issued the challenge
+        pub challenger: AccountId,
+        /// The `AccountId` of the hero that was challenged
+        pub opponent: AccountId,
+        /// The amount of gold each hero puts up
+        pub wager: TokenBalance,
+    }
+
+    /// A duel was accepted by the opponent
+    #[ink(event)]
+    pub struct DuelAccepted {
+        /// The id of the duel
+        pub duel_id: DuelId,
+        /// The `AccountId` of the hero that takes the first turn
+        pub first_turn: AccountId,
+    }
+
+    /// A duel was declined by the opponent or withdrawn by the challenger
+    #[ink(event)]
+    pub struct DuelDeclined {
+        /// The id of the duel
+        pub duel_id: DuelId,
+        /// The `AccountId` of the hero that declined
+        pub declined_by: AccountId,
+    }
+
+    /// A hero took a turn in a duel
+    #[ink(event)]
+    pub struct DuelAdvanced {
+        /// The id of the duel
+        pub duel_id: DuelId,
+        /// The `AccountId` of the hero that took the turn
+        pub hero_id: AccountId,
+        /// The turn number of the duel
+        pub turn_number: u32,
+        /// The damage dealt to the other hero
+        pub damage_dealt: u32,
+    }
+
+    /// A duel ended
+    #[ink(event)]
+    pub struct DuelEnded {
+        /// The id of the duel
+        pub duel_id: DuelId,
+        /// The `AccountId` of the hero that won
+        pub winner: AccountId,
+        /// The `AccountId` of the hero that lost
+        pub loser: AccountId,
+        /// The total number of turns the duel took
+        pub turn_count: u32,
+        /// The amount of gold paid to the winner
+        pub prize: TokenBalance,
+        /// The new rating of the winner
+        pub winner_rating: u32,
+        /// The new rating of the loser
+        pub loser_rating: u32,
+    }
+
+    /// A season ended
+    #[ink(event)]
+    pub struct SeasonEnded {
+        /// The season that ended
+        pub season: u32,
+        /// The heroes that received a trophy, from first place down
+        pub trophy_winners: Vec<AccountId>,
+    }
+
+    /// Gold was paid out of the treasury by a treasurer
+    #[ink(event)]
+    pub struct TreasuryPaid {
+        /// The account that received the gold
+        pub recipient: AccountId,
+        /// The amount of gold paid
+        pub amount: TokenBalance,
+        /// The treasurer that paid it
+        pub sender: AccountId,
     }
 
     /// A weapon was purchased
@@ -81,6 +312,84 @@ mod game {
         pub strength: u32,
     }
 
+    /// An item was listed on the marketplace
+    #[ink(event)]
+    pub struct ItemListed {
+        /// The account selling the item
+        pub seller: AccountId,
+        /// The `TokenId` of the item
+        pub token_id: TokenId,
+        /// The price in gold
+        pub price: TokenBalance,
+    }
+
+    /// A marketplace listing was cancelled and the item was returned to the seller
+    #[ink(event)]
+    pub struct ListingCancelled {
+        /// The account that listed the item
+        pub seller: AccountId,
+        /// The `TokenId` of the item
+        pub token_id: TokenId,
+    }
+
+    /// An item was sold to the shop or on the marketplace
+    #[ink(event)]
+    pub struct ItemSold {
+        /// The account that sold the item
+        pub seller: AccountId,
+        /// The account that bought the item. `None` if it was sold to the shop.
+        pub buyer: Option<AccountId>,
+        /// The `TokenId` of the item
+        pub token_id: TokenId,
+        /// The amount of gold paid for the item
+        pub price: TokenBalance,
+    }
+
+    /// Two weapons were forged into a new one
+    #[ink(event)]
+    pub struct WeaponForged {
+        /// The `AccountId` of the hero
+        pub hero_id: AccountId,
+        /// The `TokenId` of the first weapon, which was burned
+        pub weapon_a: TokenId,
+        /// The strength of the first weapon
+        pub strength_a: u32,
+        /// The `TokenId` of the second weapon, which was burned
+        pub weapon_b: TokenId,
+        /// The strength of the second weapon
+        pub strength_b: u32,
+        /// The `TokenId` of the forged weapon
+        pub token_id: TokenId,
+        /// The strength of the forged weapon
+        pub strength: u32,
+    }
+
+    /// A hero attempted to upgrade a weapon
+    #[ink(event)]
+    pub struct WeaponUpgraded {
+        /// The `AccountId` of the hero
+        pub hero_id: AccountId,
+        /// The `TokenId` of the weapon
+        pub token_id: TokenId,
+        /// True if the upgrade succeeded. The gold is spent either way.
+        pub success: bool,
+        /// The upgrade level of the weapon after the attempt
+        pub upgrade_level: u32,
+        /// The strength of the weapon after the attempt
+        pub strength: u32,
+    }
+
+    /// A weapon's durability was restored
+    #[ink(event)]
+    pub struct WeaponRepaired {
+        /// The `AccountId` of the hero
+        pub hero_id: AccountId,
+        /// The `TokenId` of the weapon
+        pub token_id: TokenId,
+        /// The amount of gold paid for the repair
+        pub cost: TokenBalance,
+    }
+
     /// A hero rested
     #[ink(event)]
     pub struct Rested {
@@ -105,20 +414,63 @@ mod game {
     pub struct Game {
         /// The configuration for the game
         config: Config,
+        /// The version of `config`. Incremented each time it changes.
+        config_version: u32,
+        /// Recent versions of the config by version number. Limited to `CONFIG_HISTORY_SIZE`.
+        config_history: Mapping<u32, Config>,
+        /// A map of scheduled config mutations by id
+        config_schedules: Mapping<ConfigScheduleId, ConfigSchedule>,
+        /// The ids of all scheduled config mutations in the order they are due. Limited to `MAX_CONFIG_SCHEDULES`.
+        config_schedule_ids: Vec<ConfigScheduleId>,
+        /// The id used for the next scheduled config mutation
+        next_config_schedule_id: ConfigScheduleId,
         /// The owner of the contract
         owner: AccountId,
+        /// The account proposed as the new owner. It becomes the owner once it accepts.
+        pending_owner: Option<AccountId>,
+        /// The roles granted to each account
+        roles: Mapping<(AccountId, Role), ()>,
+        /// The features that are currently paused
+        paused_features: Mapping<Feature, ()>,
         /// The collection id that all tokens of the game use
         collection_id: CollectionId,
         /// The id of the token used as gold
         gold_token_id: TokenId,
         /// The id of the collection used for all tokens
         next_token_id: TokenId,
+        /// The gold held by the contract that was spent by heroes and not burned
+        treasury_balance: TokenBalance,
         /// The nonce used for randomness
         random_nonce: u32,
         /// The seed used for randomness
         random_seed: u32,
         /// A map of heroes by account id
         heroes: Mapping<AccountId, Hero>,
+        /// A map of enemy templates by id
+        enemy_templates: Mapping<EnemyTypeId, EnemyTemplate>,
+        /// The ids of all enemy templates, because `enemy_templates` can't be iterated
+        enemy_template_ids: Vec<EnemyTypeId>,
+        /// A map of open duels by id
+        duels: Mapping<DuelId, Duel>,
+        /// The id used for the next duel
+        next_duel_id: DuelId,
+        /// The highest rated heroes, sorted by rating in descending order. Limited to `leaderboard_size`.
+        leaderboard: Vec<LeaderboardEntry>,
+        /// A map of marketplace listings by the `TokenId` of the listed item
+        listings: Mapping<TokenId, Listing>,
+        /// The ids of all listed items by position. Removing a listing moves the last one into its position.
+        listing_ids: Mapping<u32, TokenId>,
+        /// The position of each listed item in `listing_ids`
+        listing_positions: Mapping<TokenId, u32>,
+        /// The number of listed items
+        listing_count: u32,
+        /// The current season, starting at 1
+        season: u32,
+        /// The heroes with the most victories this season, sorted in descending order. Limited to
+        /// `leaderboard_size`.
+        season_leaderboard: Vec<SeasonLeaderboardEntry>,
+        /// The final season leaderboard of each season that ended
+        season_results: Mapping<u32, Vec<SeasonLeaderboardEntry>>,
     }
 
     impl Game {
@@ -128,7 +480,8 @@ mod game {
         /// * `gold_token_id` - The id of the token used as gold
         /// * `initial_token_id` - The first token id used for NFTs. This will be incremented for each token.
         /// * `random_seed` - A value used to differentiate randomness between games
-        /// * `config` - The config used for the game. If not provided, it will use default values.
+        /// * `config` - The config used for the game. If not provided, it will use default values. Panics if it
+        /// is invalid.
         ///
         #[ink(constructor)]
         pub fn new(
@@ -142,68 +495,384 @@ mod game {
                 gold_token_id, initial_token_id,
                 "gold_token_id and initial_token_id must be different"
             );
+            let config = config.unwrap_or_default();
+            if let Err(error) = config.validate() {
+                panic!("invalid config: {:?}", error);
+            }
 
             ink::utils::initialize_contract(|contract: &mut Self| {
                 contract.owner = Self::env().caller();
                 contract.collection_id = collection_id;
                 contract.gold_token_id = gold_token_id;
                 contract.next_token_id = initial_token_id;
-                contract.config = config.unwrap_or_default();
+                contract.config_history.insert(0, &config);
+                contract.config = config;
                 contract.random_seed = random_seed;
+                contract.season = 1;
             })
         }
 
-        /// Transfer ownership of the contract to `new_owner`
+        /// Propose `new_owner` as the owner of the contract. Ownership is transferred once `new_owner` calls
+        /// `accept_ownership`. Replaces any previous proposal.
         #[ink(message)]
-        pub fn transfer_contract_ownership(&mut self, new_owner: AccountId) -> Result<()> {
+        pub fn propose_owner(&mut self, new_owner: AccountId) -> Result<()> {
             // make sure the owner is the caller
-            if self.env().caller() != self.owner {
+            self.ensure_owner()?;
+
+            // store the proposal
+            self.pending_owner = Some(new_owner);
+
+            // emit event
+            self.env().emit_event(OwnershipProposed {
+                owner: self.owner,
+                pending_owner: new_owner,
+            });
+
+            Ok(())
+        }
+
+        /// Accept ownership of the contract. Only callable by the pending owner.
+        #[ink(message)]
+        pub fn accept_ownership(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            let pending_owner = self.pending_owner.ok_or(Error::NoPendingOwner)?;
+            if caller != pending_owner {
                 return Err(Error::NoPermission);
             }
 
             // change the owner
-            self.owner = new_owner;
+            let previous_owner = self.owner;
+            self.owner = pending_owner;
+            self.pending_owner = None;
+
+            // emit event
+            self.env().emit_event(OwnershipTransferred {
+                previous_owner,
+                new_owner: pending_owner,
+            });
 
             Ok(())
         }
 
-        /// Modify the configuration of the game. Only callable by the owner.
+        /// Cancel the proposal of a new owner. Only callable by the owner.
         #[ink(message)]
-        pub fn mutate_config(&mut self, mutation: ConfigMutation) -> Result<()> {
+        pub fn cancel_ownership_proposal(&mut self) -> Result<()> {
             // make sure the owner is the caller
-            if self.env().caller() != self.owner {
-                return Err(Error::NoPermission);
+            self.ensure_owner()?;
+
+            let pending_owner = self.pending_owner.take().ok_or(Error::NoPendingOwner)?;
+
+            // emit event
+            self.env()
+                .emit_event(OwnershipProposalCancelled { pending_owner });
+
+            Ok(())
+        }
+
+        /// Returns the owner of the contract
+        #[ink(message)]
+        pub fn get_owner(&self) -> AccountId {
+            self.owner
+        }
+
+        /// Returns the account proposed as the new owner, if there is one
+        #[ink(message)]
+        pub fn get_pending_owner(&self) -> Option<AccountId> {
+            self.pending_owner
+        }
+
+        /// Grant `role` to `account_id`. Only callable by an admin.
+        #[ink(message)]
+        pub fn grant_role(&mut self, account_id: AccountId, role: Role) -> Result<()> {
+            self.ensure_role(Role::Admin)?;
+
+            if !self.has_role(account_id, role) {
+                self.roles.insert((account_id, role), &());
+                self.env().emit_event(RoleGranted {
+                    account_id,
+                    role,
+                    sender: self.env().caller(),
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Revoke `role` from `account_id`. Only callable by an admin.
+        #[ink(message)]
+        pub fn revoke_role(&mut self, account_id: AccountId, role: Role) -> Result<()> {
+            self.ensure_role(Role::Admin)?;
+            self.remove_role(account_id, role);
+            Ok(())
+        }
+
+        /// Give up `role` for the caller
+        #[ink(message)]
+        pub fn renounce_role(&mut self, role: Role) -> Result<()> {
+            self.remove_role(self.env().caller(), role);
+            Ok(())
+        }
+
+        /// Returns true if `account_id` has `role`. The owner has every role.
+        #[ink(message)]
+        pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+            account_id == self.owner || self.roles.get((account_id, role)).is_some()
+        }
+
+        /// Pause `feature`, blocking its messages until it is unpaused. Only callable by a pauser.
+        #[ink(message)]
+        pub fn pause(&mut self, feature: Feature) -> Result<()> {
+            self.ensure_role(Role::Pauser)?;
+
+            if self.paused_features.get(feature).is_none() {
+                self.paused_features.insert(feature, &());
+                self.env().emit_event(Paused {
+                    feature,
+                    sender: self.env().caller(),
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Unpause `feature`. Only callable by a pauser.
+        #[ink(message)]
+        pub fn unpause(&mut self, feature: Feature) -> Result<()> {
+            self.ensure_role(Role::Pauser)?;
+
+            if self.paused_features.get(feature).is_some() {
+                self.paused_features.remove(feature);
+                self.env().emit_event(Unpaused {
+                    feature,
+                    sender: self.env().caller(),
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Returns true if the messages of `feature` are blocked, either because it or `Feature::All` is paused
+        #[ink(message)]
+        pub fn is_paused(&self, feature: Feature) -> bool {
+            self.paused_features.get(Feature::All).is_some()
+                || self.paused_features.get(feature).is_some()
+        }
+
+        /// Modify the configuration of the game. Only callable by a config manager. Nothing is changed if the
+        /// resulting config is invalid.
+        #[ink(message)]
+        pub fn mutate_config(&mut self, mutation: ConfigMutation) -> Result<()> {
+            // make sure the caller can change the config
+            self.ensure_role(Role::ConfigManager)?;
+
+            // apply the mutation to a copy
+            let mut config = self.config.clone();
+            mutation.apply_to(&mut config);
+
+            self.set_config(config, Some(self.env().caller()), None)
+        }
+
+        /// Change the config back to the one with `version` from the history. The rollback is recorded as a
+        /// new version. Only callable by an admin.
+        #[ink(message)]
+        pub fn rollback_config(&mut self, version: u32) -> Result<()> {
+            self.ensure_role(Role::Admin)?;
+
+            let config = self
+                .config_history
+                .get(version)
+                .ok_or(Error::ConfigVersionNotFound)?;
+            self.set_config(config, Some(self.env().caller()), None)
+        }
+
+        /// Schedule `mutation` to be applied at `activate_at_block` and reverted at `revert_at_block`. Due
+        /// mutations are applied before each gameplay message runs. Reverting restores the fields set by
+        /// `mutation` to their values from before it was applied. Up to `MAX_CONFIG_SCHEDULES` can be pending. Only
+        /// callable by a config manager.
+        #[ink(message)]
+        pub fn schedule_config_mutation(
+            &mut self,
+            mutation: ConfigMutation,
+            activate_at_block: BlockNumber,
+            revert_at_block: Option<BlockNumber>,
+        ) -> Result<ConfigScheduleId> {
+            self.ensure_role(Role::ConfigManager)?;
+
+            // the revert must come after the activation
+            if revert_at_block.map_or(false, |block| block <= activate_at_block) {
+                return Err(Error::InvalidSchedule);
+            }
+            if self.config_schedule_ids.len() >= MAX_CONFIG_SCHEDULES {
+                return Err(Error::TooManyConfigSchedules);
+            }
+
+            // make sure the mutation is valid for the current config
+            let mut config = self.config.clone();
+            mutation.clone().apply_to(&mut config);
+            config.validate()?;
+
+            // store the schedule
+            let schedule_id = self.next_config_schedule_id;
+            self.next_config_schedule_id += 1;
+            self.insert_config_schedule(&ConfigSchedule {
+                id: schedule_id,
+                mutation,
+                activate_at_block,
+                revert_at_block,
+                revert_mutation: None,
+            });
+
+            // emit event
+            self.env().emit_event(ConfigMutationScheduled {
+                schedule_id,
+                activate_at_block,
+                revert_at_block,
+            });
+
+            Ok(schedule_id)
+        }
+
+        /// Cancel a scheduled config mutation. If it was already applied, it is reverted immediately. Only
+        /// callable by a config manager.
+        #[ink(message)]
+        pub fn cancel_config_schedule(&mut self, schedule_id: ConfigScheduleId) -> Result<()> {
+            self.ensure_role(Role::ConfigManager)?;
+
+            let schedule = self
+                .config_schedules
+                .get(schedule_id)
+                .ok_or(Error::ConfigScheduleNotFound)?;
+
+            // revert the mutation if it was applied
+            if let Some(revert_mutation) = schedule.revert_mutation {
+                let mut config = self.config.clone();
+                revert_mutation.apply_to(&mut config);
+                self.set_config(config, Some(self.env().caller()), Some(schedule_id))?;
             }
+            self.remove_config_schedule(schedule_id);
 
-            // apply the mutation
-            mutation.apply_to(&mut self.config);
+            // emit event
+            self.env()
+                .emit_event(ConfigScheduleCancelled { schedule_id });
 
             Ok(())
         }
 
+        /// Returns the scheduled config mutations that have not been applied, or that have been applied and
+        /// are waiting to be reverted, in the order they are due
+        #[ink(message)]
+        pub fn get_config_schedules(&self) -> Vec<ConfigSchedule> {
+            self.config_schedule_ids
+                .iter()
+                .filter_map(|&id| self.config_schedules.get(id))
+                .collect()
+        }
+
         /// Returns the game's config
         #[ink(message)]
         pub fn get_config(&self) -> Config {
             self.config.clone()
         }
 
-        /// Returns the token type and id of a token id
+        /// Returns the version of the game's config
+        #[ink(message)]
+        pub fn get_config_version(&self) -> u32 {
+            self.config_version
+        }
+
+        /// Returns the config with `version` if it's still in the history
+        #[ink(message)]
+        pub fn get_config_history(&self, version: u32) -> Option<Config> {
+            self.config_history.get(version)
+        }
+
+        /// Add `template` to the bestiary. The bestiary can hold up to `MAX_ENEMY_TEMPLATES`. Only callable by a
+        /// config manager.
+        #[ink(message)]
+        pub fn add_enemy_template(&mut self, template: EnemyTemplate) -> Result<()> {
+            self.ensure_role(Role::ConfigManager)?;
+            template.validate()?;
+
+            // make sure the id is not taken and there is room for it
+            if self.enemy_templates.get(template.id).is_some() {
+                return Err(Error::EnemyTemplateAlreadyExists);
+            }
+            if self.enemy_template_ids.len() >= MAX_ENEMY_TEMPLATES {
+                return Err(Error::TooManyEnemyTemplates);
+            }
+
+            // add the template
+            self.enemy_templates.insert(template.id, &template);
+            self.enemy_template_ids.push(template.id);
+
+            Ok(())
+        }
+
+        /// Replace the template in the bestiary with the same id as `template`. Only callable by a config
+        /// manager.
+        #[ink(message)]
+        pub fn update_enemy_template(&mut self, template: EnemyTemplate) -> Result<()> {
+            self.ensure_role(Role::ConfigManager)?;
+            template.validate()?;
+
+            // make sure the template exists
+            if self.enemy_templates.get(template.id).is_none() {
+                return Err(Error::EnemyTemplateNotFound);
+            }
+
+            // replace the template
+            self.enemy_templates.insert(template.id, &template);
+
+            Ok(())
+        }
+
+        /// Stop the template with `id` from spawning. Only callable by a config manager.
+        #[ink(message)]
+        pub fn disable_enemy_template(&mut self, id: EnemyTypeId) -> Result<()> {
+            self.ensure_role(Role::ConfigManager)?;
+
+            let mut template = self
+                .enemy_templates
+                .get(id)
+                .ok_or(Error::EnemyTemplateNotFound)?;
+            template.enabled = false;
+            self.enemy_templates.insert(id, &template);
+
+            Ok(())
+        }
+
+        /// Returns all templates in the bestiary, including disabled ones
+        #[ink(message)]
+        pub fn get_enemy_templates(&self) -> Vec<EnemyTemplate> {
+            self.enemy_template_ids
+                .iter()
+                .filter_map(|id| self.enemy_templates.get(id))
+                .collect()
+        }
+
+        /// Returns the token type, rarity, and id of a token id
         #[ink(message)]
         pub fn get_token_info(&self, token_id: TokenId) -> TokenIdInfo {
             let token_id = WrappedTokenId(token_id);
             TokenIdInfo {
                 token_type: token_id.token_type(),
+                rarity: token_id.rarity(),
                 id: token_id.id(),
             }
         }
 
-        /// Create a hero for the caller
+        /// Create a hero for the caller. Each account can only have one hero.
         #[ink(message)]
         pub fn create_hero(&mut self) -> Result<Hero> {
+            self.before_gameplay(Feature::Heroes)?;
+
             let caller = self.env().caller();
+            if self.heroes.get(caller).is_some() {
+                return Err(Error::HeroAlreadyExists);
+            }
 
             // mint the weapon token
-            let weapon_id = self.mint_nft(caller, TokenType::Weapon, true)?;
+            let weapon_id = self.mint_nft(caller, TokenType::Weapon, true, None)?;
 
             // add attribute to equipment tokens
             let weapon_strength = self
@@ -214,6 +883,7 @@ mod game {
                 self.config.hero_max_health,
                 weapon_id,
                 self.config.hero_initial_potion_count,
+                self.config.pvp_initial_rating,
             );
             self.heroes.insert(caller, &hero);
 
@@ -226,50 +896,158 @@ mod game {
             Ok(hero)
         }
 
+        /// Commit to the hash of a secret for the caller's next battle. Required before `start_battle` if
+        /// commit-reveal is enabled in the config. The commitment can't be replaced until a battle is started
+        /// with it.
+        /// ### Parameters
+        /// * `commitment` - The hash of the secret that will be revealed in `start_battle`. Each secret revealed
+        /// after it must be the preimage of the one before it, so the hero commits to a hash chain up front and
+        /// reveals one link for the enemy and each round.
+        ///
+        #[ink(message)]
+        pub fn commit_battle(&mut self, commitment: [u8; 32]) -> Result<()> {
+            self.before_gameplay(Feature::Battles)?;
+
+            let caller = self.env().caller();
+            let mut hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
+            if hero.commitment.is_some() {
+                return Err(Error::CommitmentAlreadyMade);
+            }
+
+            hero.commitment = Some(self.commit(commitment));
+            self.heroes.insert(caller, &hero);
+
+            Ok(())
+        }
+
         /// Start a battle with a randomly generated enemy
+        /// ### Parameters
+        /// * `reveal` - Required if the hero made a commitment with `commit_battle`. Its hash must match the
+        /// commitment, and it must be revealed in a later block. Mixed with that block's randomness, it decides the
+        /// enemy. It becomes the commitment for the first round.
+        ///
         #[ink(message)]
-        pub fn start_battle(&mut self) -> Result<()> {
+        pub fn start_battle(&mut self, reveal: Option<[u8; 32]>) -> Result<()> {
+            self.before_gameplay(Feature::Battles)?;
+
             let caller = self.env().caller();
             let mut hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
+            if hero.is_in_battle() {
+                return Err(Error::HeroIsInBattle);
+            }
+
+            // reveal the committed secret and commit to it for the first round
+            let (commitment, mut secret) = match hero.commitment.take() {
+                Some(commitment) => {
+                    let (revealed, secret) = self.reveal_secret(commitment, reveal)?;
+                    (Some(self.commit(revealed)), Some(secret))
+                }
+                None if self.config.commit_reveal_enabled => return Err(Error::CommitmentRequired),
+                None => (None, None),
+            };
+
+            // pick a template from the bestiary, otherwise use the config
+            let template = self.random_enemy_template(secret.as_mut());
+            let (template_id, health_range, strength_range, mut gold_drop_range, mut hat_chance) =
+                match template {
+                    Some(template) => (
+                        Some(template.id),
+                        template.health_range,
+                        template.strength_range,
+                        template.gold_drop_range,
+                        template.hat_chance,
+                    ),
+                    None => (
+                        None,
+                        self.config.enemy_health_range,
+                        self.config.enemy_strength_range,
+                        self.config.enemy_gold_drop_range,
+                        self.config.enemy_wearing_hat_chance,
+                    ),
+                };
+
+            // a boss is fought each time the victory count reaches a multiple of the threshold
+            let threshold = self.config.boss_victory_threshold;
+            let is_boss = threshold > 0
+                && hero.consecutive_victory_count > 0
+                && hero.consecutive_victory_count % threshold == 0;
+            if is_boss {
+                let multiplier = self.config.boss_gold_multiplier;
+                gold_drop_range = Range::new(
+                    gold_drop_range.start.saturating_mul(multiplier),
+                    gold_drop_range.end.saturating_mul(multiplier),
+                );
+                hat_chance = 100;
+            }
 
             // possibly generate a hat for the enemy
             let hat_id = {
-                if self.random_chance(self.config.enemy_wearing_hat_chance) {
+                if self.random_chance(hat_chance, secret.as_mut()) {
                     // the hat is owned by the contract
-                    Some(self.mint_nft(self.env().account_id(), TokenType::Hat, false)?)
+                    let hat_id = self.mint_nft(
+                        self.env().account_id(),
+                        TokenType::Hat,
+                        false,
+                        secret.as_mut(),
+                    )?;
+                    self.add_hat_attribute(hat_id, self.config.hat_defense_range, secret.as_mut())?;
+                    Some(hat_id)
                 } else {
                     None
                 }
             };
 
             // create the enemy
+            let mut health = self.random_in_range(health_range, secret.as_mut());
+            let mut strength = self.random_in_range(strength_range, secret.as_mut());
+            if is_boss {
+                health = health.saturating_mul(self.config.boss_stat_multiplier);
+                strength = strength.saturating_mul(self.config.boss_stat_multiplier);
+            }
+            let experience = health
+                .saturating_mul(self.config.experience_per_enemy_health)
+                .saturating_add(strength.saturating_mul(self.config.experience_per_enemy_strength));
             let enemy = Enemy {
                 hat_id,
-                health: self.random_in_range(self.config.enemy_health_range),
-                strength: self.random_in_range(self.config.enemy_strength_range),
+                health,
+                strength,
+                experience,
+                gold_drop_range,
+                template_id,
+                is_boss,
             };
 
             // update the data
-            hero.battle = Some(Battle::new(enemy));
+            hero.battle = Some(Battle::new(enemy, commitment));
             self.heroes.insert(caller, &hero);
 
             // emit the event
             self.env().emit_event(BattleStarted {
                 hero_id: caller,
                 enemy,
+                template_id,
+                is_boss,
             });
 
             Ok(())
         }
 
         /// Advance the battle to the next turn
-        #[ink(message)]
-        pub fn advance_battle(&mut self, command: Command) -> Result<()> {
+        /// ### Parameters
+        /// * `command` - The action the hero takes
+        /// * `reveal` - Required if the battle was started with a commitment. Its hash must match the
+        /// commitment, and it must be revealed in a later block. Mixed with that block's randomness, it decides
+        /// this round. It becomes the commitment for the next round.
+        ///
+        #[ink(message)]
+        pub fn advance_battle(&mut self, command: Command, reveal: Option<[u8; 32]>) -> Result<()> {
             /// Returns true if the battle is over
             fn battle_is_over(hero: &Hero, battle: &Battle) -> bool {
-                hero.is_dead() || battle.enemy.is_dead()
+                hero.is_dead() || battle.enemy.is_dead() || battle.hero_fled
             }
 
+            self.before_gameplay(Feature::Battles)?;
+
             // setup
             let caller = self.env().caller();
             let mut hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
@@ -277,17 +1055,28 @@ mod game {
             let hero_initial_health = hero.health;
             let enemy_initial_health = battle.enemy.health;
 
+            // reveal the secret for this round and commit to it for the next one
+            let mut secret = match battle.commitment {
+                Some(commitment) => {
+                    let (revealed, secret) = self.reveal_secret(commitment, reveal)?;
+                    battle.commitment = Some(self.commit(revealed));
+                    Some(secret)
+                }
+                None => None,
+            };
+
             // perform actions
-            let hero_goes_first = self.random_chance(self.config.hero_goes_first_chance);
+            let hero_goes_first =
+                self.random_chance(self.config.hero_goes_first_chance, secret.as_mut());
             if hero_goes_first {
-                self.hero_action(&mut hero, &mut battle, command)?;
+                self.hero_action(&mut hero, &mut battle, command, secret.as_mut())?;
                 if !battle_is_over(&hero, &battle) {
-                    self.enemy_action(&mut hero, &mut battle)?;
+                    self.enemy_action(&mut hero, &mut battle, secret.as_mut())?;
                 }
             } else {
-                self.enemy_action(&mut hero, &mut battle)?;
+                self.enemy_action(&mut hero, &mut battle, secret.as_mut())?;
                 if !battle_is_over(&hero, &battle) {
-                    self.hero_action(&mut hero, &mut battle, command)?;
+                    self.hero_action(&mut hero, &mut battle, command, secret.as_mut())?;
                 }
             }
 
@@ -306,56 +1095,80 @@ mod game {
 
                 // process hero victory
                 if battle.enemy.is_dead() {
-                    // update victory count
-                    hero.consecutive_victory_count =
-                        hero.consecutive_victory_count.saturating_add(1);
-                    if hero.highest_consecutive_victory_count < hero.consecutive_victory_count {
-                        hero.highest_consecutive_victory_count = hero.consecutive_victory_count;
-                    }
+                    // update victory counts
+                    hero.record_victory(self.season);
+                    self.update_season_leaderboard(caller, hero.season_stats.victory_count);
+
+                    // give experience
+                    hero.experience = hero.experience.saturating_add(battle.enemy.experience);
+                    self.level_up(caller, &mut hero);
 
                     // give gold reward
-                    let gold_amount = self.random_in_range(self.config.enemy_gold_drop_range);
-                    self.mint_gold(gold_amount as TokenBalance)?;
+                    let gold_amount =
+                        self.random_in_range(battle.enemy.gold_drop_range, secret.as_mut());
+                    self.reward_gold(gold_amount as TokenBalance)?;
 
                     // transfer the hat to the hero if it exists
                     if let Some(hat_id) = battle.enemy.hat_id {
-                        self.env().extension().transfer(
-                            caller,
-                            self.collection_id,
-                            TransferParams::Simple {
-                                token_id: hat_id,
-                                amount: 1,
-                                keep_alive: false,
-                            },
-                        )?;
+                        self.transfer_nft(caller, hat_id)?;
+                    }
+
+                    // give a trophy for defeating a boss
+                    if battle.enemy.is_boss {
+                        self.mint_nft(caller, TokenType::Trophy, false, secret.as_mut())?;
                     }
                 }
 
                 // process hero loss
                 if hero.is_dead() {
                     // update hero stats
-                    hero.health = self.config.hero_max_health;
-                    hero.consecutive_victory_count = 0;
+                    hero.health = self.hero_max_health(&hero);
+                    hero.record_defeat();
 
                     // burn the enemy's hat if it won the battle with it
                     if let Some(hat_id) = battle.enemy.hat_id {
-                        self.env().extension().burn(
-                            self.collection_id,
-                            BurnParams {
-                                token_id: hat_id,
-                                amount: 1,
-                                keep_alive: false,
-                                remove_token_storage: true,
-                            },
-                        )?;
+                        self.burn_nft(hat_id)?;
+                    }
+                }
+
+                // process hero escape
+                if battle.hero_fled {
+                    // take as much of the gold penalty as the hero can pay
+                    let gold_penalty = self
+                        .get_gold_balance(caller)
+                        .min(self.config.flee_gold_penalty);
+                    if gold_penalty > 0 {
+                        self.collect_gold(gold_penalty)?;
+                    }
+
+                    // take health, but never enough to kill the hero
+                    let remaining_health = hero
+                        .health
+                        .saturating_sub(self.config.flee_health_penalty)
+                        .max(1);
+                    let health_penalty = hero.health.saturating_sub(remaining_health);
+                    hero.health = remaining_health;
+
+                    // the enemy left with its hat, so burn it
+                    if let Some(hat_id) = battle.enemy.hat_id {
+                        self.burn_nft(hat_id)?;
                     }
+
+                    // emit event
+                    self.env().emit_event(BattleFled {
+                        hero_id: caller,
+                        round_count: battle.round_number,
+                        gold_penalty,
+                        health_penalty,
+                    });
                 }
 
                 // emit event
                 self.env().emit_event(BattleEnded {
                     hero_id: caller,
-                    hero_wins: !hero.is_dead(),
+                    hero_wins: battle.enemy.is_dead(),
                     round_count: battle.round_number,
+                    is_boss: battle.enemy.is_boss,
                 });
             } else {
                 hero.battle = Some(battle);
@@ -367,15 +1180,304 @@ mod game {
             Ok(())
         }
 
-        /// Returns the `Hero` for `account_id` if it exists
+        /// Challenge `opponent` to a duel. The caller's wager is held by the contract until the duel ends. The
+        /// caller is locked into the duel, but the opponent is free until they accept it. Returns the id of the
+        /// duel.
+        #[ink(message)]
+        pub fn challenge(&mut self, opponent: AccountId) -> Result<DuelId> {
+            self.before_gameplay(Feature::Battles)?;
+
+            let caller = self.env().caller();
+            let mut hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
+            if hero.is_in_battle() {
+                return Err(Error::HeroIsInBattle);
+            }
+
+            // make sure the opponent can fight
+            if opponent == caller {
+                return Err(Error::CannotDuelSelf);
+            }
+            let opponent_hero = self.heroes.get(opponent).ok_or(Error::HeroNotFound)?;
+            if opponent_hero.is_in_battle() {
+                return Err(Error::OpponentIsInBattle);
+            }
+
+            // hold the wager
+            let wager = self.config.duel_wager;
+            self.escrow_gold(wager)?;
+
+            // create the duel and lock the challenger into it
+            let duel_id = self.next_duel_id;
+            self.next_duel_id = self.next_duel_id.saturating_add(1);
+            self.duels
+                .insert(duel_id, &Duel::new(caller, opponent, wager));
+            hero.duel_id = Some(duel_id);
+            self.heroes.insert(caller, &hero);
+
+            // emit event
+            self.env().emit_event(DuelChallenged {
+                duel_id,
+                challenger: caller,
+                opponent,
+                wager,
+            });
+
+            Ok(duel_id)
+        }
+
+        /// Accept the duel `duel_id` the caller was challenged to, locking the caller into it. The caller's wager
+        /// is held by the contract until the duel ends.
+        #[ink(message)]
+        pub fn accept_duel(&mut self, duel_id: DuelId) -> Result<()> {
+            self.before_gameplay(Feature::Battles)?;
+
+            let caller = self.env().caller();
+            let mut duel = self.duels.get(duel_id).ok_or(Error::DuelNotFound)?;
+
+            // only the opponent can accept
+            if caller != duel.opponent {
+                return Err(Error::NoPermission);
+            }
+            if duel.accepted {
+                return Err(Error::DuelAlreadyAccepted);
+            }
+            let mut hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
+            if hero.is_in_battle() {
+                return Err(Error::HeroIsInBattle);
+            }
+
+            // hold the wager and join the duel
+            self.escrow_gold(duel.wager)?;
+            hero.duel_id = Some(duel_id);
+            self.heroes.insert(caller, &hero);
+
+            // pick who goes first
+            duel.accepted = true;
+            duel.turn = if self.random_chance(50, None) {
+                duel.challenger
+            } else {
+                duel.opponent
+            };
+            duel.turn_deadline = Some(self.duel_turn_deadline());
+            self.duels.insert(duel_id, &duel);
+
+            // emit event
+            self.env().emit_event(DuelAccepted {
+                duel_id,
+                first_turn: duel.turn,
+            });
+
+            Ok(())
+        }
+
+        /// Decline the duel `duel_id` the caller was challenged to, or withdraw a challenge the caller issued. Can
+        /// only be done before the duel is accepted. Allowed while battles are paused, so the wager can always be
+        /// returned.
+        #[ink(message)]
+        pub fn decline_duel(&mut self, duel_id: DuelId) -> Result<()> {
+            let caller = self.env().caller();
+            let duel = self.duels.get(duel_id).ok_or(Error::DuelNotFound)?;
+            if caller != duel.challenger && caller != duel.opponent {
+                return Err(Error::NoPermission);
+            }
+            if duel.accepted {
+                return Err(Error::DuelAlreadyAccepted);
+            }
+
+            // return the challenger's wager and release the challenger
+            self.transfer_gold(duel.challenger, duel.wager)?;
+            self.end_duel(duel_id, &duel)?;
+
+            // emit event
+            self.env().emit_event(DuelDeclined {
+                duel_id,
+                declined_by: caller,
+            });
+
+            Ok(())
+        }
+
+        /// Take the caller's turn in their duel. `Command::Flee` surrenders the duel. The turn can still be taken
+        /// after the deadline, as long as the other hero has not claimed a forfeit.
+        #[ink(message)]
+        pub fn advance_duel(&mut self, command: Command) -> Result<()> {
+            self.before_gameplay(Feature::Battles)?;
+
+            let caller = self.env().caller();
+            let (duel_id, mut duel) = self.duel_of(caller)?;
+            if !duel.accepted {
+                return Err(Error::DuelNotAccepted);
+            }
+            if duel.turn != caller {
+                return Err(Error::NotYourTurn);
+            }
+            let other = duel.other(caller);
+            let mut hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
+            let mut other_hero = self.heroes.get(other).ok_or(Error::HeroNotFound)?;
+            let other_initial_health = other_hero.health;
+
+            // perform the action
+            let mut surrendered = false;
+            match command {
+                Command::Attack => {
+                    let attack_power = self
+                        .weapon_attack_power(&hero, None)?
+                        .saturating_sub(self.hat_defense(other_hero.hat_id)?);
+                    other_hero.health = other_hero.health.saturating_sub(attack_power);
+                    self.wear_weapon(hero.weapon_id)?;
+                }
+                Command::Heal => {
+                    if hero.potion_count == 0 {
+                        return Err(Error::HeroHasNoPotions);
+                    }
+                    hero.health = self.hero_max_health(&hero);
+                    hero.potion_count = hero.potion_count.saturating_sub(1);
+                }
+                Command::Flee => surrendered = true,
+            }
+
+            // send the event
+            self.env().emit_event(DuelAdvanced {
+                duel_id,
+                hero_id: caller,
+                turn_number: duel.turn_number,
+                damage_dealt: other_initial_health.saturating_sub(other_hero.health),
+            });
+            duel.turn_number = duel.turn_number.saturating_add(1);
+
+            // process duel outcome
+            if surrendered {
+                self.settle_duel(duel_id, &duel, (other, other_hero), (caller, hero))?;
+            } else if other_hero.is_dead() {
+                self.settle_duel(duel_id, &duel, (caller, hero), (other, other_hero))?;
+            } else {
+                duel.turn = other;
+                duel.turn_deadline = Some(self.duel_turn_deadline());
+                self.duels.insert(duel_id, &duel);
+                self.heroes.insert(caller, &hero);
+                self.heroes.insert(other, &other_hero);
+            }
+
+            Ok(())
+        }
+
+        /// Win the caller's duel because the other hero did not take their turn before the deadline. The caller
+        /// receives both wagers as if the other hero surrendered. Allowed while battles are paused, so a hero can
+        /// always leave a duel the other hero abandoned.
+        #[ink(message)]
+        pub fn claim_duel_forfeit(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            let (duel_id, duel) = self.duel_of(caller)?;
+            let turn_deadline = duel.turn_deadline.ok_or(Error::DuelNotAccepted)?;
+            if duel.turn == caller {
+                return Err(Error::NotYourTurn);
+            }
+            if self.env().block_number() <= turn_deadline {
+                return Err(Error::DuelTurnNotExpired);
+            }
+
+            // the hero that ran out of time loses
+            let other = duel.other(caller);
+            let hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
+            let other_hero = self.heroes.get(other).ok_or(Error::HeroNotFound)?;
+            self.settle_duel(duel_id, &duel, (caller, hero), (other, other_hero))
+        }
+
+        /// Returns the `Duel` with `duel_id` if it exists
+        #[ink(message)]
+        pub fn get_duel(&self, duel_id: DuelId) -> Option<Duel> {
+            self.duels.get(duel_id)
+        }
+
+        /// Returns up to `limit` leaderboard entries, starting at position `offset`
+        #[ink(message)]
+        pub fn get_leaderboard(&self, offset: u32, limit: u32) -> Vec<LeaderboardEntry> {
+            self.leaderboard
+                .iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .copied()
+                .collect()
+        }
+
+        /// End the current season. The heroes at the top of the season leaderboard receive a trophy, the
+        /// leaderboard is saved to the season results, and season stats start over. Only callable by an admin.
+        #[ink(message)]
+        pub fn end_season(&mut self) -> Result<()> {
+            self.ensure_role(Role::Admin)?;
+
+            // give trophies to the top finishers
+            let results = core::mem::take(&mut self.season_leaderboard);
+            let trophy_winners: Vec<AccountId> = results
+                .iter()
+                .take(self.config.season_trophy_count as usize)
+                .map(|entry| entry.hero_id)
+                .collect();
+            for &hero_id in &trophy_winners {
+                self.mint_nft(hero_id, TokenType::SeasonTrophy, false, None)?;
+            }
+
+            // save the results and start the next season
+            let season = self.season;
+            self.season_results.insert(season, &results);
+            self.season = season.saturating_add(1);
+
+            // emit event
+            self.env().emit_event(SeasonEnded {
+                season,
+                trophy_winners,
+            });
+
+            Ok(())
+        }
+
+        /// Returns the current season
+        #[ink(message)]
+        pub fn get_season(&self) -> u32 {
+            self.season
+        }
+
+        /// Returns up to `limit` entries of the current season's leaderboard, starting at position `offset`
+        #[ink(message)]
+        pub fn get_season_leaderboard(
+            &self,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<SeasonLeaderboardEntry> {
+            self.season_leaderboard
+                .iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .copied()
+                .collect()
+        }
+
+        /// Returns the final leaderboard of `season` if it has ended
+        #[ink(message)]
+        pub fn get_season_results(&self, season: u32) -> Option<Vec<SeasonLeaderboardEntry>> {
+            self.season_results.get(season)
+        }
+
+        /// Returns the `HeroInfo` for `account_id` if the hero exists
         #[ink(message)]
-        pub fn get_hero(&self, account_id: AccountId) -> Option<Hero> {
-            self.heroes.get(account_id)
+        pub fn get_hero(&self, account_id: AccountId) -> Option<HeroInfo> {
+            self.heroes.get(account_id).map(|hero| HeroInfo {
+                hero,
+                max_health: self.hero_max_health(&hero),
+                next_level_experience: self
+                    .config
+                    .level_growth_table
+                    .get(hero.level as usize - 1)
+                    .map(|level| level.experience_required),
+                season_stats: hero.season_stats(self.season),
+            })
         }
 
         /// Equip `token_id` for the caller
         #[ink(message)]
         pub fn equip(&mut self, token_id: TokenId) -> Result<()> {
+            self.before_gameplay(Feature::Equipment)?;
+
             let caller = self.env().caller();
             let mut hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
 
@@ -389,6 +1491,9 @@ mod game {
                 return Err(Error::InvalidEquipment);
             }
 
+            // make sure the caller holds the token and it's not equipped already
+            self.ensure_owns_unfrozen_token(caller, token_id)?;
+
             // set equipment and prepare thaw
             let thaw_token_id: Option<TokenId>;
             match token_type {
@@ -400,6 +1505,7 @@ mod game {
                     thaw_token_id = hero.hat_id;
                     hero.hat_id = Some(token_id)
                 }
+                TokenType::Trophy | TokenType::SeasonTrophy => return Err(Error::InvalidEquipment),
             }
 
             // thaw previous token if needed
@@ -432,10 +1538,10 @@ mod game {
         /// Remove the caller's hat
         #[ink(message)]
         pub fn unequip_hat(&mut self) -> Result<()> {
+            self.before_gameplay(Feature::Equipment)?;
+
             let caller = self.env().caller();
-            let mut hero = self
-                .get_hero(self.env().caller())
-                .ok_or(Error::HeroNotFound)?;
+            let mut hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
 
             // remove the hat
             if let Some(hat_id) = hero.hat_id {
@@ -468,8 +1574,8 @@ mod game {
                 attribute_key(),
             ) {
                 Ok(Some(
-                    Decode::decode(&mut &attribute.value[..])
-                        .map_err(|_| Error::AttributeDecodeFailed)?,
+                    TokenMetadata::decode_attribute(&attribute.value)
+                        .ok_or(Error::AttributeDecodeFailed)?,
                 ))
             } else {
                 Ok(None)
@@ -479,6 +1585,8 @@ mod game {
         /// Purchase a healing potion. Can only be done outside of battle.
         #[ink(message)]
         pub fn buy_potion(&mut self, quantity: u32) -> Result<()> {
+            self.before_gameplay(Feature::Shop)?;
+
             let mut hero =
                 self.spend_gold(self.config.potion_cost.saturating_mul(quantity as _))?;
 
@@ -493,11 +1601,13 @@ mod game {
         /// Returns the `TokenId` of the generated weapon.
         #[ink(message)]
         pub fn buy_weapon(&mut self) -> Result<TokenId> {
+            self.before_gameplay(Feature::Shop)?;
+
             let caller = self.env().caller();
             self.spend_gold(self.config.weapon_cost)?;
 
             // generate the weapon
-            let token_id = self.mint_nft(caller, TokenType::Weapon, false)?;
+            let token_id = self.mint_nft(caller, TokenType::Weapon, false, None)?;
             let strength = self
                 .add_equipment_attribute(token_id, self.config.purchased_weapon_strength_range)?;
             self.env().emit_event(WeaponPurchased {
@@ -509,179 +1619,1178 @@ mod game {
             Ok(token_id)
         }
 
-        /// Recover the caller to full health. Can only be done outside of battle.
+        /// Burn `weapon_a` and `weapon_b` to forge a new weapon. Both must be held by the caller and not equipped.
+        /// The new weapon has `forge_strength_percentage` of their combined strength, plus a bonus from
+        /// `forge_bonus_strength_range`. Can only be done outside of battle.
         #[ink(message)]
-        pub fn rest(&mut self) -> Result<()> {
+        pub fn forge(&mut self, weapon_a: TokenId, weapon_b: TokenId) -> Result<TokenId> {
+            self.before_gameplay(Feature::Shop)?;
+
             let caller = self.env().caller();
-            let mut hero = self.spend_gold(self.config.rest_cost)?;
+            if weapon_a == weapon_b {
+                return Err(Error::CannotForgeSameWeapon);
+            }
 
-            // set health to max
-            hero.health = self.config.hero_max_health;
-            self.heroes.insert(self.env().caller(), &hero);
+            // make sure both are unequipped weapons with metadata
+            let mut strengths = [0; 2];
+            for (strength, token_id) in strengths.iter_mut().zip([weapon_a, weapon_b]) {
+                if WrappedTokenId(token_id).token_type() != Some(TokenType::Weapon) {
+                    return Err(Error::InvalidEquipment);
+                }
+                self.ensure_owns_unfrozen_token(caller, token_id)?;
+                *strength = self
+                    .get_metadata(token_id)?
+                    .ok_or(Error::InvalidEquipment)?
+                    .strength;
+            }
+            let [strength_a, strength_b] = strengths;
+
+            self.spend_gold(self.config.forge_cost)?;
+
+            // burn both weapons
+            for token_id in [weapon_a, weapon_b] {
+                self.escrow_nft(token_id)?;
+                self.burn_nft(token_id)?;
+            }
+
+            // generate the new weapon
+            let base_strength = (strength_a as u64 + strength_b as u64)
+                * self.config.forge_strength_percentage as u64
+                / 100;
+            let bonus_strength = self.random_in_range(self.config.forge_bonus_strength_range, None);
+            let strength = base_strength
+                .saturating_add(bonus_strength as u64)
+                .min(u32::MAX as u64) as u32;
+            let token_id = self.mint_nft(caller, TokenType::Weapon, false, None)?;
+            let metadata = self.new_weapon_metadata(token_id, strength);
+            self.set_metadata(token_id, &metadata)?;
 
             // emit event
-            self.env().emit_event(Rested { hero_id: caller });
+            self.env().emit_event(WeaponForged {
+                hero_id: caller,
+                weapon_a,
+                strength_a,
+                weapon_b,
+                strength_b,
+                token_id,
+                strength: metadata.strength,
+            });
 
-            Ok(())
+            Ok(token_id)
         }
 
-        /// Returns the balance of gold for `account_id`
+        /// Attempt to upgrade the weapon `token_id` to its next level in `weapon_upgrade_table`, paying the cost of
+        /// the level. The weapon can be equipped. Returns false if the upgrade failed. Can only be done outside of
+        /// battle.
         #[ink(message)]
-        pub fn get_gold_balance(&self, account_id: AccountId) -> TokenBalance {
-            self.env()
-                .extension()
-                .balance_of(self.collection_id, self.gold_token_id, account_id)
-        }
-    }
+        pub fn upgrade_weapon(&mut self, token_id: TokenId) -> Result<bool> {
+            self.before_gameplay(Feature::Shop)?;
 
-    // helper functions
-    impl Game {
-        /// Returns the current token id and increments `next_token_id`
-        fn increment_next_token_id(&mut self) -> TokenId {
-            let token_id = self.next_token_id;
-            self.next_token_id += 1;
-            token_id
-        }
+            let caller = self.env().caller();
 
-        /// Mints a non-fungible token to `recipient`. Freezes it if `freeze` is true.
-        fn mint_nft(
-            &mut self,
-            recipient: AccountId,
-            token_type: TokenType,
-            freeze: bool,
-        ) -> Result<TokenId> {
-            let id = self.increment_next_token_id();
-            let token_id = WrappedTokenId::new(id, Some(token_type)).0;
+            // make sure it's a weapon held by the caller
+            if WrappedTokenId(token_id).token_type() != Some(TokenType::Weapon) {
+                return Err(Error::InvalidEquipment);
+            }
+            self.ensure_owns_token(caller, token_id)?;
+            let mut metadata = self
+                .get_metadata(token_id)?
+                .ok_or(Error::InvalidEquipment)?;
 
-            let params = MintParams::CreateToken {
-                token_id,
-                initial_supply: 1,
-                unit_price: self.env().extension().get_token_account_deposit(),
-                cap: Some(TokenCap::SingleMint),
-                royalty: None,
-            };
-            self.env()
-                .extension()
-                .mint(recipient, self.collection_id, params)?;
-            if freeze {
-                self.env().extension().freeze(Freeze {
-                    collection_id: self.collection_id,
-                    freeze_type: FreezeType::Token(token_id),
-                })?;
+            // pay for the next level
+            let upgrade = *self
+                .config
+                .weapon_upgrade_table
+                .get(metadata.upgrade_level as usize)
+                .ok_or(Error::WeaponAtMaxLevel)?;
+            self.spend_gold(upgrade.cost)?;
+
+            // upgrade the weapon unless it fails
+            let success = !self.random_chance(upgrade.failure_chance, None);
+            if success {
+                metadata.strength = metadata.strength.saturating_add(upgrade.strength_increase);
+                metadata.upgrade_level += 1;
+                self.set_metadata(token_id, &metadata)?;
             }
-            Ok(token_id)
-        }
 
-        /// Mints `amount` gold to the caller
-        fn mint_gold(&mut self, amount: TokenBalance) -> Result<()> {
-            let params = MintParams::Mint {
-                token_id: self.gold_token_id,
-                amount,
-                unit_price: None,
-            };
-            self.env()
-                .extension()
-                .mint(self.env().caller(), self.collection_id, params)?;
-            Ok(())
-        }
+            // emit event
+            self.env().emit_event(WeaponUpgraded {
+                hero_id: caller,
+                token_id,
+                success,
+                upgrade_level: metadata.upgrade_level,
+                strength: metadata.strength,
+            });
 
-        /// Adds `TokenMetadata` to `token_id` and sets `token_type`. Generates strength value if `strength_range` is
-        /// Some. Returns the generated strength.
-        fn add_equipment_attribute(
-            &mut self,
-            token_id: TokenId,
-            strength_range: Range,
-        ) -> Result<u32> {
-            let strength = self.random_in_range(strength_range);
-            let metadata = TokenMetadata { strength };
-            self.env().extension().set_attribute(
-                self.collection_id,
-                Some(token_id),
-                attribute_key(),
-                metadata.encode(),
-            )?;
-            Ok(strength)
+            Ok(success)
         }
 
-        /// Spends `amount` gold from the caller's account. Returns the hero.
-        fn spend_gold(&mut self, amount: TokenBalance) -> Result<Hero> {
+        /// Restore the durability of the weapon `token_id`, paying `repair_cost_per_point` for each point restored.
+        /// The weapon can be equipped, but must be damaged. Returns the cost. Can only be done outside of battle.
+        #[ink(message)]
+        pub fn repair_weapon(&mut self, token_id: TokenId) -> Result<TokenBalance> {
+            self.before_gameplay(Feature::Shop)?;
+
             let caller = self.env().caller();
 
-            // make sure hero is not in a battle
-            let hero = self.get_hero(caller).ok_or(Error::HeroNotFound)?;
-            if hero.battle.is_some() {
-                return Err(Error::HeroIsInBattle);
+            // make sure it's a weapon held by the caller that can break
+            if WrappedTokenId(token_id).token_type() != Some(TokenType::Weapon) {
+                return Err(Error::InvalidEquipment);
             }
-
-            // check the balance
-            let gold_balance =
-                self.env()
-                    .extension()
-                    .balance_of(self.collection_id, self.gold_token_id, caller);
-            if gold_balance < amount {
-                return Err(Error::NotEnoughGold);
+            self.ensure_owns_token(caller, token_id)?;
+            let mut metadata = self
+                .get_metadata(token_id)?
+                .ok_or(Error::InvalidEquipment)?;
+            let durability = metadata.durability.ok_or(Error::WeaponHasNoDurability)?;
+            if durability.current >= durability.max {
+                return Err(Error::WeaponNotDamaged);
             }
 
-            // transfer gold to the contract
-            self.env().extension().transfer(
-                self.env().account_id(),
-                self.collection_id,
-                TransferParams::Operator {
-                    token_id: self.gold_token_id,
-                    source: self.env().caller(),
-                    amount,
-                    keep_alive: true,
-                },
-            )?;
+            // pay for the missing durability
+            let cost = self
+                .config
+                .repair_cost_per_point
+                .saturating_mul(durability.max.saturating_sub(durability.current) as _);
+            self.spend_gold(cost)?;
 
-            // burn the token units
-            let params = BurnParams {
-                token_id: self.gold_token_id,
-                amount,
-                keep_alive: false,
-                remove_token_storage: false,
-            };
-            self.env().extension().burn(self.collection_id, params)?;
+            // restore the durability
+            metadata.durability = Some(Durability::new(durability.max));
+            self.set_metadata(token_id, &metadata)?;
+
+            // emit event
+            self.env().emit_event(WeaponRepaired {
+                hero_id: caller,
+                token_id,
+                cost,
+            });
+
+            Ok(cost)
+        }
+
+        /// Recover the caller to full health. Can only be done outside of battle.
+        #[ink(message)]
+        pub fn rest(&mut self) -> Result<()> {
+            self.before_gameplay(Feature::Shop)?;
+
+            let caller = self.env().caller();
+            let mut hero = self.spend_gold(self.config.rest_cost)?;
+
+            // set health to max
+            hero.health = self.hero_max_health(&hero);
+            self.heroes.insert(self.env().caller(), &hero);
+
+            // emit event
+            self.env().emit_event(Rested { hero_id: caller });
+
+            Ok(())
+        }
+
+        /// Sell the weapon or hat `token_id` to the shop. It must be held by the caller and not equipped. Returns
+        /// the amount of gold paid, which is based on the item's strength and defense. It's paid from the treasury,
+        /// and only minted if `item_sales_mint_shortfall` is true and the treasury can't cover it.
+        #[ink(message)]
+        pub fn sell_item(&mut self, token_id: TokenId) -> Result<TokenBalance> {
+            self.before_gameplay(Feature::Shop)?;
+
+            let caller = self.env().caller();
+            self.ensure_sellable_item(caller, token_id)?;
+
+            // price the item before its metadata is removed
+            let metadata = self.get_metadata(token_id)?.unwrap_or_default();
+            let points = metadata.strength.saturating_add(metadata.defense);
+            let price = self.config.item_sell_base_price.saturating_add(
+                self.config
+                    .item_sell_price_per_point
+                    .saturating_mul(points as _),
+            );
+
+            // make sure the seller can be paid
+            let treasury_amount = self.treasury_balance.min(price);
+            if treasury_amount < price && !self.config.item_sales_mint_shortfall {
+                return Err(Error::NotEnoughTreasuryGold);
+            }
+
+            // take the item and burn it
+            self.escrow_nft(token_id)?;
+            self.burn_nft(token_id)?;
+
+            // pay the seller from the treasury, minting what it can't cover
+            self.pay_from_treasury(caller, treasury_amount)?;
+            if price > treasury_amount {
+                self.mint_gold(price - treasury_amount)?;
+            }
+
+            // emit event
+            self.env().emit_event(ItemSold {
+                seller: caller,
+                buyer: None,
+                token_id,
+                price,
+            });
+
+            Ok(price)
+        }
+
+        /// List the weapon or hat `token_id` on the marketplace for `price` gold. It must be held by the caller
+        /// and not equipped. The item is held by the contract until it's sold or the listing is cancelled.
+        #[ink(message)]
+        pub fn list_item(&mut self, token_id: TokenId, price: TokenBalance) -> Result<()> {
+            self.before_gameplay(Feature::Marketplace)?;
+
+            let caller = self.env().caller();
+            self.ensure_sellable_item(caller, token_id)?;
+
+            // hold the item and store the listing
+            self.escrow_nft(token_id)?;
+            self.listings.insert(
+                token_id,
+                &Listing {
+                    seller: caller,
+                    token_id,
+                    price,
+                },
+            );
+            self.listing_ids.insert(self.listing_count, &token_id);
+            self.listing_positions.insert(token_id, &self.listing_count);
+            self.listing_count += 1;
+
+            // emit event
+            self.env().emit_event(ItemListed {
+                seller: caller,
+                token_id,
+                price,
+            });
+
+            Ok(())
+        }
+
+        /// Cancel the listing of `token_id` and return the item to the seller. Only callable by the seller. Allowed
+        /// while the marketplace is paused, so sellers can always get their items back.
+        #[ink(message)]
+        pub fn cancel_listing(&mut self, token_id: TokenId) -> Result<()> {
+            let listing = self.listings.get(token_id).ok_or(Error::ListingNotFound)?;
+            if listing.seller != self.env().caller() {
+                return Err(Error::NoPermission);
+            }
+
+            // return the item
+            self.remove_listing(token_id);
+            self.transfer_nft(listing.seller, token_id)?;
+
+            // emit event
+            self.env().emit_event(ListingCancelled {
+                seller: listing.seller,
+                token_id,
+            });
+
+            Ok(())
+        }
+
+        /// Buy the item listed as `token_id`. The price is paid to the seller, minus `marketplace_fee_percentage`
+        /// which is kept in the treasury.
+        #[ink(message)]
+        pub fn buy_listing(&mut self, token_id: TokenId) -> Result<()> {
+            self.before_gameplay(Feature::Marketplace)?;
+
+            let caller = self.env().caller();
+            let listing = self.listings.get(token_id).ok_or(Error::ListingNotFound)?;
+            if listing.seller == caller {
+                return Err(Error::CannotBuyOwnListing);
+            }
+            if self.get_gold_balance(caller) < listing.price {
+                return Err(Error::NotEnoughGold);
+            }
+
+            // pay the seller and the fee
+            let fee = listing
+                .price
+                .saturating_mul(self.config.marketplace_fee_percentage as _)
+                / 100;
+            self.transfer_gold_from_caller(listing.seller, listing.price - fee)?;
+            self.escrow_gold(fee)?;
+            self.treasury_balance = self.treasury_balance.saturating_add(fee);
+
+            // give the item to the buyer
+            self.remove_listing(token_id);
+            self.transfer_nft(caller, token_id)?;
+
+            // emit event
+            self.env().emit_event(ItemSold {
+                seller: listing.seller,
+                buyer: Some(caller),
+                token_id,
+                price: listing.price,
+            });
+
+            Ok(())
+        }
+
+        /// Returns up to `limit` marketplace listings, starting at position `offset`. Removing a listing moves the
+        /// last listing into its position.
+        #[ink(message)]
+        pub fn get_listings(&self, offset: u32, limit: u32) -> Vec<Listing> {
+            (offset..offset.saturating_add(limit).min(self.listing_count))
+                .filter_map(|position| self.listing_ids.get(position))
+                .filter_map(|token_id| self.listings.get(token_id))
+                .collect()
+        }
+
+        /// Transfer `amount` gold from the treasury to `recipient`. Only callable by a treasurer.
+        #[ink(message)]
+        pub fn withdraw_treasury(
+            &mut self,
+            recipient: AccountId,
+            amount: TokenBalance,
+        ) -> Result<()> {
+            self.distribute_treasury(ink_prelude::vec![(recipient, amount)])
+        }
+
+        /// Transfer gold from the treasury to each recipient in `payments`. Nothing is paid if the treasury
+        /// can't cover all of them. Only callable by a treasurer.
+        #[ink(message)]
+        pub fn distribute_treasury(
+            &mut self,
+            payments: Vec<(AccountId, TokenBalance)>,
+        ) -> Result<()> {
+            self.ensure_role(Role::Treasurer)?;
+
+            // make sure the treasury can cover every payment
+            let total = payments
+                .iter()
+                .fold(0 as TokenBalance, |total, (_, amount)| {
+                    total.saturating_add(*amount)
+                });
+            if self.treasury_balance < total {
+                return Err(Error::NotEnoughTreasuryGold);
+            }
+
+            // pay each recipient
+            for (recipient, amount) in payments {
+                self.pay_from_treasury(recipient, amount)?;
+                self.env().emit_event(TreasuryPaid {
+                    recipient,
+                    amount,
+                    sender: self.env().caller(),
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Returns the amount of gold in the treasury
+        #[ink(message)]
+        pub fn get_treasury_balance(&self) -> TokenBalance {
+            self.treasury_balance
+        }
+
+        /// Returns the balance of gold for `account_id`
+        #[ink(message)]
+        pub fn get_gold_balance(&self, account_id: AccountId) -> TokenBalance {
+            self.env()
+                .extension()
+                .balance_of(self.collection_id, self.gold_token_id, account_id)
+        }
+    }
+
+    // helper functions
+    impl Game {
+        /// Returns `Error::NoPermission` if the caller is not the owner
+        fn ensure_owner(&self) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NoPermission);
+            }
+            Ok(())
+        }
+
+        /// Returns `Error::NoPermission` if the caller does not have `role`
+        fn ensure_role(&self, role: Role) -> Result<()> {
+            if !self.has_role(self.env().caller(), role) {
+                return Err(Error::NoPermission);
+            }
+            Ok(())
+        }
+
+        /// Prepares the game for a gameplay message of `feature`. Returns `Error::GamePaused` if it is paused,
+        /// otherwise applies the scheduled config mutations that are due.
+        fn before_gameplay(&mut self, feature: Feature) -> Result<()> {
+            self.ensure_not_paused(feature)?;
+            self.apply_config_schedules();
+            Ok(())
+        }
+
+        /// Applies and reverts scheduled config mutations that are due at the current block. Schedules that are
+        /// finished are removed. A mutation that would make the config invalid is skipped, and
+        /// `ConfigScheduleFailed` is emitted.
+        fn apply_config_schedules(&mut self) {
+            let block_number = self.env().block_number();
+
+            // the schedules are in the order they are due, so stop at the first one that isn't
+            while let Some(&schedule_id) = self.config_schedule_ids.first() {
+                let mut schedule = match self.config_schedules.get(schedule_id) {
+                    Some(schedule) if block_number < schedule.due_block() => break,
+                    Some(schedule) => schedule,
+                    None => {
+                        self.config_schedule_ids.remove(0);
+                        continue;
+                    }
+                };
+                self.remove_config_schedule(schedule_id);
+
+                // revert the mutation
+                if let Some(revert_mutation) = schedule.revert_mutation {
+                    let mut config = self.config.clone();
+                    revert_mutation.apply_to(&mut config);
+                    self.set_scheduled_config(config, schedule_id);
+                    continue;
+                }
+
+                // apply the mutation, remembering how to revert it
+                let mut config = self.config.clone();
+                schedule.revert_mutation = Some(schedule.mutation.revert_from(&config));
+                schedule.mutation.clone().apply_to(&mut config);
+                if self.set_scheduled_config(config, schedule_id)
+                    && schedule.revert_at_block.is_some()
+                {
+                    // wait for the revert block
+                    self.insert_config_schedule(&schedule);
+                }
+            }
+        }
+
+        /// Sets `config` for the schedule with `schedule_id`. Returns false and emits `ConfigScheduleFailed` if
+        /// the config is invalid.
+        fn set_scheduled_config(&mut self, config: Config, schedule_id: ConfigScheduleId) -> bool {
+            match self.set_config(config, None, Some(schedule_id)) {
+                Ok(()) => true,
+                Err(error) => {
+                    self.env()
+                        .emit_event(ConfigScheduleFailed { schedule_id, error });
+                    false
+                }
+            }
+        }
+
+        /// Stores `schedule`, keeping `config_schedule_ids` in the order the schedules are due. It goes after
+        /// the schedules due at the same block.
+        fn insert_config_schedule(&mut self, schedule: &ConfigSchedule) {
+            let due_block = schedule.due_block();
+            let index = self.config_schedule_ids.partition_point(|&id| {
+                self.config_schedules
+                    .get(id)
+                    .map_or(true, |other| other.due_block() <= due_block)
+            });
+            self.config_schedule_ids.insert(index, schedule.id);
+            self.config_schedules.insert(schedule.id, schedule);
+        }
+
+        /// Removes the scheduled config mutation with `schedule_id`
+        fn remove_config_schedule(&mut self, schedule_id: ConfigScheduleId) {
+            self.config_schedules.remove(schedule_id);
+            self.config_schedule_ids.retain(|&id| id != schedule_id);
+        }
+
+        /// Returns `Error::GamePaused` if `feature` is paused
+        fn ensure_not_paused(&self, feature: Feature) -> Result<()> {
+            if self.is_paused(feature) {
+                return Err(Error::GamePaused);
+            }
+            Ok(())
+        }
+
+        /// Removes `role` from `account_id` if it was granted
+        fn remove_role(&mut self, account_id: AccountId, role: Role) {
+            if self.roles.get((account_id, role)).is_some() {
+                self.roles.remove((account_id, role));
+                self.env().emit_event(RoleRevoked {
+                    account_id,
+                    role,
+                    sender: self.env().caller(),
+                });
+            }
+        }
+
+        /// Validates `config` and replaces the current config with it as a new version, removing the oldest
+        /// version from the history. Does nothing if no fields changed. `sender` and `schedule_id` are recorded in
+        /// the event.
+        fn set_config(
+            &mut self,
+            config: Config,
+            sender: Option<AccountId>,
+            schedule_id: Option<ConfigScheduleId>,
+        ) -> Result<()> {
+            config.validate()?;
+
+            let changes = self.config.changes_to(&config);
+            if changes.is_empty() {
+                return Ok(());
+            }
+
+            // record the new version
+            self.config_version += 1;
+            self.config_history.insert(self.config_version, &config);
+            if let Some(oldest_version) = self.config_version.checked_sub(CONFIG_HISTORY_SIZE) {
+                self.config_history.remove(oldest_version);
+            }
+            self.config = config;
+
+            // emit event
+            self.env().emit_event(ConfigUpdated {
+                version: self.config_version,
+                changes,
+                sender,
+                schedule_id,
+            });
+
+            Ok(())
+        }
+
+        /// Picks an enabled template from the bestiary, weighted by spawn weight. Returns `None` if there are
+        /// no templates that can spawn.
+        fn random_enemy_template(
+            &mut self,
+            secret: Option<&mut SecretRandomness>,
+        ) -> Option<EnemyTemplate> {
+            let templates: Vec<EnemyTemplate> = self
+                .get_enemy_templates()
+                .into_iter()
+                .filter(|template| template.enabled && template.spawn_weight > 0)
+                .collect();
+            let total_weight = templates.iter().fold(0_u32, |total, template| {
+                total.saturating_add(template.spawn_weight)
+            });
+            if total_weight == 0 {
+                return None;
+            }
+
+            // find the template the roll lands on
+            let mut roll = self.random_in_range((0, total_weight - 1).into(), secret);
+            templates.into_iter().find(|template| {
+                if roll < template.spawn_weight {
+                    true
+                } else {
+                    roll -= template.spawn_weight;
+                    false
+                }
+            })
+        }
+
+        /// Picks a rarity from `rarity_tiers`, weighted by the weight of each tier. Returns `None` if there are no
+        /// tiers that can be picked.
+        fn random_rarity(&mut self, secret: Option<&mut SecretRandomness>) -> Option<Rarity> {
+            let total_weight = self
+                .config
+                .rarity_tiers
+                .iter()
+                .fold(0_u32, |total, tier| total.saturating_add(tier.weight));
+            if total_weight == 0 {
+                return None;
+            }
+
+            // find the tier the roll lands on
+            let mut roll = self.random_in_range((0, total_weight - 1).into(), secret);
+            let index = self.config.rarity_tiers.iter().position(|tier| {
+                if roll < tier.weight {
+                    true
+                } else {
+                    roll -= tier.weight;
+                    false
+                }
+            })?;
+            Rarity::from_value(index as u8 + 1)
+        }
+
+        /// Returns the tier of `token_id`'s rarity, if it has one
+        fn rarity_tier(&self, token_id: TokenId) -> Option<RarityTier> {
+            let rarity = WrappedTokenId(token_id).rarity()?;
+            self.config.rarity_tiers.get(rarity.tier_index()).copied()
+        }
+
+        /// Returns the current token id and increments `next_token_id`
+        fn increment_next_token_id(&mut self) -> TokenId {
+            let token_id = self.next_token_id;
+            self.next_token_id += 1;
+            token_id
+        }
+
+        /// Mints a non-fungible token to `recipient`. Freezes it if `freeze` is true. Weapons and hats are given a
+        /// random rarity, which is encoded in the `TokenId`.
+        fn mint_nft(
+            &mut self,
+            recipient: AccountId,
+            token_type: TokenType,
+            freeze: bool,
+            secret: Option<&mut SecretRandomness>,
+        ) -> Result<TokenId> {
+            let id = self.increment_next_token_id();
+            let mut wrapped_token_id = WrappedTokenId::new(id, Some(token_type));
+            if matches!(token_type, TokenType::Weapon | TokenType::Hat) {
+                wrapped_token_id.set_rarity(self.random_rarity(secret));
+            }
+            let token_id = wrapped_token_id.0;
+
+            let params = MintParams::CreateToken {
+                token_id,
+                initial_supply: 1,
+                unit_price: self.env().extension().get_token_account_deposit(),
+                cap: Some(TokenCap::SingleMint),
+                royalty: None,
+            };
+            self.env()
+                .extension()
+                .mint(recipient, self.collection_id, params)?;
+            if freeze {
+                self.env().extension().freeze(Freeze {
+                    collection_id: self.collection_id,
+                    freeze_type: FreezeType::Token(token_id),
+                })?;
+            }
+            Ok(token_id)
+        }
+
+        /// Mints `amount` gold to the caller
+        fn mint_gold(&mut self, amount: TokenBalance) -> Result<()> {
+            let params = MintParams::Mint {
+                token_id: self.gold_token_id,
+                amount,
+                unit_price: None,
+            };
+            self.env()
+                .extension()
+                .mint(self.env().caller(), self.collection_id, params)?;
+            Ok(())
+        }
+
+        /// Returns true if `token_id` is frozen
+        fn is_frozen(&self, token_id: TokenId) -> bool {
+            self.env()
+                .extension()
+                .token_of(self.collection_id, token_id)
+                .map(|token| token.is_frozen)
+                .unwrap_or_default()
+        }
+
+        /// Returns `Error::TokenNotOwned` if `account_id` does not hold `token_id`
+        fn ensure_owns_token(&self, account_id: AccountId, token_id: TokenId) -> Result<()> {
+            let balance =
+                self.env()
+                    .extension()
+                    .balance_of(self.collection_id, token_id, account_id);
+            if balance == 0 {
+                return Err(Error::TokenNotOwned);
+            }
+            Ok(())
+        }
+
+        /// Returns `Error::TokenNotOwned` if `account_id` does not hold `token_id`, or `Error::TokenIsFrozen` if
+        /// it's frozen
+        fn ensure_owns_unfrozen_token(
+            &self,
+            account_id: AccountId,
+            token_id: TokenId,
+        ) -> Result<()> {
+            self.ensure_owns_token(account_id, token_id)?;
+            if self.is_frozen(token_id) {
+                return Err(Error::TokenIsFrozen);
+            }
+            Ok(())
+        }
+
+        /// Returns `Error::ItemNotSellable` if `token_id` is not a weapon or hat, or an error from
+        /// `ensure_owns_unfrozen_token` if `account_id` can't sell it
+        fn ensure_sellable_item(&self, account_id: AccountId, token_id: TokenId) -> Result<()> {
+            if !matches!(
+                WrappedTokenId(token_id).token_type(),
+                Some(TokenType::Weapon | TokenType::Hat)
+            ) {
+                return Err(Error::ItemNotSellable);
+            }
+            self.ensure_owns_unfrozen_token(account_id, token_id)
+        }
+
+        /// Transfers the non-fungible token `token_id` from the caller to the contract
+        fn escrow_nft(&mut self, token_id: TokenId) -> Result<()> {
+            self.env().extension().transfer(
+                self.env().account_id(),
+                self.collection_id,
+                TransferParams::Operator {
+                    token_id,
+                    source: self.env().caller(),
+                    amount: 1,
+                    keep_alive: false,
+                },
+            )?;
+            Ok(())
+        }
+
+        /// Transfers the non-fungible token `token_id` held by the contract to `recipient`
+        fn transfer_nft(&mut self, recipient: AccountId, token_id: TokenId) -> Result<()> {
+            self.env().extension().transfer(
+                recipient,
+                self.collection_id,
+                TransferParams::Simple {
+                    token_id,
+                    amount: 1,
+                    keep_alive: false,
+                },
+            )?;
+            Ok(())
+        }
+
+        /// Removes the marketplace listing of `token_id`, moving the last listing into its position
+        fn remove_listing(&mut self, token_id: TokenId) {
+            self.listings.remove(token_id);
+            let position = match self.listing_positions.get(token_id) {
+                Some(position) => position,
+                None => return,
+            };
+            self.listing_positions.remove(token_id);
+
+            // move the last listing into the empty position
+            self.listing_count -= 1;
+            if position != self.listing_count {
+                if let Some(last_id) = self.listing_ids.get(self.listing_count) {
+                    self.listing_ids.insert(position, &last_id);
+                    self.listing_positions.insert(last_id, &position);
+                }
+            }
+            self.listing_ids.remove(self.listing_count);
+        }
+
+        /// Burns `token_id`, which must be owned by the contract
+        fn burn_nft(&mut self, token_id: TokenId) -> Result<()> {
+            self.env().extension().burn(
+                self.collection_id,
+                BurnParams {
+                    token_id,
+                    amount: 1,
+                    keep_alive: false,
+                    remove_token_storage: true,
+                },
+            )?;
+            Ok(())
+        }
+
+        /// Adds `TokenMetadata` to `token_id` and sets `token_type`. Generates strength value from `strength_range`,
+        /// or from the strength range of its rarity if it has one. Returns the generated strength.
+        fn add_equipment_attribute(
+            &mut self,
+            token_id: TokenId,
+            strength_range: Range,
+        ) -> Result<u32> {
+            let strength_range = self
+                .rarity_tier(token_id)
+                .map_or(strength_range, |tier| tier.strength_range);
+            let strength = self.random_in_range(strength_range, None);
+            let metadata = self.new_weapon_metadata(token_id, strength);
+            self.set_metadata(token_id, &metadata)?;
+            Ok(strength)
+        }
+
+        /// Returns the `TokenMetadata` for the new weapon `token_id` with `strength`. It has durability generated
+        /// from `weapon_durability_range` if it's set.
+        fn new_weapon_metadata(&mut self, token_id: TokenId, strength: u32) -> TokenMetadata {
+            let durability = self
+                .config
+                .weapon_durability_range
+                .map(|range| Durability::new(self.random_in_range(range, None)));
+            TokenMetadata {
+                strength,
+                durability,
+                rarity: WrappedTokenId(token_id).rarity(),
+                ..Default::default()
+            }
+        }
+
+        /// Adds `TokenMetadata` to the hat `token_id` with a defense value generated from `defense_range`, or from
+        /// the defense range of its rarity if it has one. Returns the generated defense.
+        fn add_hat_attribute(
+            &mut self,
+            token_id: TokenId,
+            defense_range: Range,
+            secret: Option<&mut SecretRandomness>,
+        ) -> Result<u32> {
+            let defense_range = self
+                .rarity_tier(token_id)
+                .map_or(defense_range, |tier| tier.defense_range);
+            let defense = self.random_in_range(defense_range, secret);
+            self.set_metadata(
+                token_id,
+                &TokenMetadata {
+                    defense,
+                    rarity: WrappedTokenId(token_id).rarity(),
+                    ..Default::default()
+                },
+            )?;
+            Ok(defense)
+        }
+
+        /// Stores `metadata` as the equipment attribute of `token_id`, replacing the existing one
+        fn set_metadata(&mut self, token_id: TokenId, metadata: &TokenMetadata) -> Result<()> {
+            self.env().extension().set_attribute(
+                self.collection_id,
+                Some(token_id),
+                attribute_key(),
+                metadata.encode_attribute(),
+            )?;
+            Ok(())
+        }
+
+        /// Returns the defense of `hat_id`. A missing hat or a hat without metadata has no defense.
+        fn hat_defense(&self, hat_id: Option<TokenId>) -> Result<u32> {
+            match hat_id {
+                Some(hat_id) => Ok(self
+                    .get_metadata(hat_id)?
+                    .map(|metadata| metadata.defense)
+                    .unwrap_or_default()),
+                None => Ok(0),
+            }
+        }
+
+        /// Spends `amount` gold from the caller's account. Returns the hero.
+        fn spend_gold(&mut self, amount: TokenBalance) -> Result<Hero> {
+            let caller = self.env().caller();
+
+            // make sure hero is not in a battle
+            let hero = self.heroes.get(caller).ok_or(Error::HeroNotFound)?;
+            if hero.is_in_battle() {
+                return Err(Error::HeroIsInBattle);
+            }
+
+            // take the gold
+            self.collect_gold(amount)?;
 
             Ok(hero)
         }
 
+        /// Takes `amount` gold from the caller's account. `gold_burn_percentage` of it is burned and the rest is
+        /// kept in the treasury.
+        fn collect_gold(&mut self, amount: TokenBalance) -> Result<()> {
+            // transfer gold to the contract
+            self.escrow_gold(amount)?;
+
+            // burn the token units
+            let burn_amount = amount.saturating_mul(self.config.gold_burn_percentage as _) / 100;
+            if burn_amount > 0 {
+                let params = BurnParams {
+                    token_id: self.gold_token_id,
+                    amount: burn_amount,
+                    keep_alive: false,
+                    remove_token_storage: false,
+                };
+                self.env().extension().burn(self.collection_id, params)?;
+            }
+
+            // keep the rest
+            self.treasury_balance = self.treasury_balance.saturating_add(amount - burn_amount);
+
+            Ok(())
+        }
+
+        /// Gives `amount` gold to the caller. If `enemy_drops_from_treasury` is true, it's paid from the treasury
+        /// and only the amount the treasury can't cover is minted.
+        fn reward_gold(&mut self, amount: TokenBalance) -> Result<()> {
+            let treasury_amount = if self.config.enemy_drops_from_treasury {
+                self.treasury_balance.min(amount)
+            } else {
+                0
+            };
+            self.pay_from_treasury(self.env().caller(), treasury_amount)?;
+            if amount > treasury_amount {
+                self.mint_gold(amount - treasury_amount)?;
+            }
+            Ok(())
+        }
+
+        /// Transfers `amount` gold from the treasury to `recipient`. Returns `Error::NotEnoughTreasuryGold` if
+        /// the treasury can't cover it.
+        fn pay_from_treasury(&mut self, recipient: AccountId, amount: TokenBalance) -> Result<()> {
+            if self.treasury_balance < amount {
+                return Err(Error::NotEnoughTreasuryGold);
+            }
+            self.transfer_gold(recipient, amount)?;
+            self.treasury_balance -= amount;
+            Ok(())
+        }
+
+        /// Transfers `amount` gold from the caller's account to the contract. Returns `Error::NotEnoughGold` if
+        /// the caller can't afford it.
+        fn escrow_gold(&mut self, amount: TokenBalance) -> Result<()> {
+            self.transfer_gold_from_caller(self.env().account_id(), amount)
+        }
+
+        /// Transfers `amount` gold from the caller's account to `recipient`. Returns `Error::NotEnoughGold` if the
+        /// caller can't afford it.
+        fn transfer_gold_from_caller(
+            &mut self,
+            recipient: AccountId,
+            amount: TokenBalance,
+        ) -> Result<()> {
+            if amount == 0 {
+                return Ok(());
+            }
+            if self.get_gold_balance(self.env().caller()) < amount {
+                return Err(Error::NotEnoughGold);
+            }
+            self.env().extension().transfer(
+                recipient,
+                self.collection_id,
+                TransferParams::Operator {
+                    token_id: self.gold_token_id,
+                    source: self.env().caller(),
+                    amount,
+                    keep_alive: true,
+                },
+            )?;
+            Ok(())
+        }
+
+        /// Transfers `amount` gold held by the contract to `recipient`
+        fn transfer_gold(&mut self, recipient: AccountId, amount: TokenBalance) -> Result<()> {
+            if amount == 0 {
+                return Ok(());
+            }
+            self.env().extension().transfer(
+                recipient,
+                self.collection_id,
+                TransferParams::Simple {
+                    token_id: self.gold_token_id,
+                    amount,
+                    keep_alive: false,
+                },
+            )?;
+            Ok(())
+        }
+
+        /// Returns the id and `Duel` that `hero_id` is engaged in
+        fn duel_of(&self, hero_id: AccountId) -> Result<(DuelId, Duel)> {
+            let hero = self.heroes.get(hero_id).ok_or(Error::HeroNotFound)?;
+            let duel_id = hero.duel_id.ok_or(Error::HeroNotInDuel)?;
+            let duel = self.duels.get(duel_id).ok_or(Error::HeroNotInDuel)?;
+            Ok((duel_id, duel))
+        }
+
+        /// Returns the deadline of a duel turn that starts at the current block
+        fn duel_turn_deadline(&self) -> BlockNumber {
+            self.env()
+                .block_number()
+                .saturating_add(self.config.duel_turn_timeout)
+        }
+
+        /// Ends `duel` with a win for `winner` and a loss for `loser`, which are each the id and hero of a
+        /// participant. Updates both ratings and pays both wagers to the winner.
+        fn settle_duel(
+            &mut self,
+            duel_id: DuelId,
+            duel: &Duel,
+            winner: (AccountId, Hero),
+            loser: (AccountId, Hero),
+        ) -> Result<()> {
+            let (winner, mut winner_hero) = winner;
+            let (loser, mut loser_hero) = loser;
+
+            // the loser recovers like a hero that lost a battle
+            loser_hero.health = self.hero_max_health(&loser_hero);
+
+            // update ratings
+            let rating_delta = elo_rating_delta(
+                winner_hero.rating,
+                loser_hero.rating,
+                self.config.pvp_rating_k_factor,
+            );
+            winner_hero.rating = winner_hero.rating.saturating_add(rating_delta);
+            loser_hero.rating = loser_hero.rating.saturating_sub(rating_delta);
+            self.update_leaderboard(winner, winner_hero.rating);
+            self.update_leaderboard(loser, loser_hero.rating);
+            self.heroes.insert(winner, &winner_hero);
+            self.heroes.insert(loser, &loser_hero);
+
+            // pay both wagers to the winner and release both heroes
+            let prize = duel.wager.saturating_mul(2);
+            self.transfer_gold(winner, prize)?;
+            self.end_duel(duel_id, duel)?;
+
+            // emit event
+            self.env().emit_event(DuelEnded {
+                duel_id,
+                winner,
+                loser,
+                turn_count: duel.turn_number,
+                prize,
+                winner_rating: winner_hero.rating,
+                loser_rating: loser_hero.rating,
+            });
+
+            Ok(())
+        }
+
+        /// Removes `duel` and releases both heroes from it
+        fn end_duel(&mut self, duel_id: DuelId, duel: &Duel) -> Result<()> {
+            for hero_id in [duel.challenger, duel.opponent] {
+                // the opponent is only in the duel if it was accepted
+                let mut hero = self.heroes.get(hero_id).ok_or(Error::HeroNotFound)?;
+                if hero.duel_id == Some(duel_id) {
+                    hero.duel_id = None;
+                    self.heroes.insert(hero_id, &hero);
+                }
+            }
+            self.duels.remove(duel_id);
+            Ok(())
+        }
+
+        /// Moves `hero_id` to its position for `rating` on the leaderboard. Heroes that fall outside of
+        /// `leaderboard_size` are dropped until their rating changes again.
+        fn update_leaderboard(&mut self, hero_id: AccountId, rating: u32) {
+            self.leaderboard.retain(|entry| entry.hero_id != hero_id);
+            let index = self
+                .leaderboard
+                .iter()
+                .position(|entry| entry.rating < rating)
+                .unwrap_or(self.leaderboard.len());
+            self.leaderboard
+                .insert(index, LeaderboardEntry { hero_id, rating });
+            self.leaderboard
+                .truncate(self.config.leaderboard_size as usize);
+        }
+
+        /// Moves `hero_id` to its position for `victory_count` on the season leaderboard. Heroes with the same
+        /// count keep the order they reached it in.
+        fn update_season_leaderboard(&mut self, hero_id: AccountId, victory_count: u32) {
+            self.season_leaderboard
+                .retain(|entry| entry.hero_id != hero_id);
+            let index = self
+                .season_leaderboard
+                .iter()
+                .position(|entry| entry.victory_count < victory_count)
+                .unwrap_or(self.season_leaderboard.len());
+            self.season_leaderboard.insert(
+                index,
+                SeasonLeaderboardEntry {
+                    hero_id,
+                    victory_count,
+                },
+            );
+            self.season_leaderboard
+                .truncate(self.config.leaderboard_size as usize);
+        }
+
+        /// Returns the attack power of `hero`'s equipped weapon
+        fn weapon_attack_power(
+            &mut self,
+            hero: &Hero,
+            secret: Option<&mut SecretRandomness>,
+        ) -> Result<u32> {
+            let metadata = self
+                .get_metadata(hero.weapon_id)?
+                .ok_or(Error::InvalidEquipment)?;
+            let attack_power = self.calculate_attack_power(metadata.strength, secret);
+
+            // a broken weapon deals reduced damage
+            if metadata.durability.map_or(false, |x| x.is_broken()) {
+                return Ok(
+                    (attack_power as u64 * self.config.broken_weapon_damage_percentage as u64 / 100)
+                        as u32,
+                );
+            }
+            Ok(attack_power)
+        }
+
+        /// Reduces the durability of `weapon_id` by 1, if it has durability left
+        fn wear_weapon(&mut self, weapon_id: TokenId) -> Result<()> {
+            if let Some(mut metadata) = self.get_metadata(weapon_id)? {
+                if let Some(durability) = metadata.durability.as_mut() {
+                    if !durability.is_broken() {
+                        durability.current -= 1;
+                        self.set_metadata(weapon_id, &metadata)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+
         /// Handles `hero`'s action in `battle`, according to `command`.
         fn hero_action(
             &mut self,
             hero: &mut Hero,
             battle: &mut Battle,
             command: Command,
+            secret: Option<&mut SecretRandomness>,
         ) -> Result<()> {
             match command {
                 Command::Attack => {
-                    let metadata = self
-                        .get_metadata(hero.weapon_id)?
-                        .ok_or(Error::InvalidEquipment)?;
-                    let attack_power = self.calculate_attack_power(metadata.strength);
+                    let attack_power = self
+                        .weapon_attack_power(hero, secret)?
+                        .saturating_sub(self.hat_defense(battle.enemy.hat_id)?);
                     battle.enemy.health = battle.enemy.health.saturating_sub(attack_power);
+                    self.wear_weapon(hero.weapon_id)?;
                 }
                 Command::Heal => {
                     if hero.potion_count == 0 {
                         return Err(Error::HeroHasNoPotions);
                     }
-                    hero.health = self.config.hero_max_health;
+                    hero.health = self.hero_max_health(hero);
                     hero.potion_count = hero.potion_count.saturating_sub(1);
                 }
+                Command::Flee => {
+                    battle.hero_fled = self.random_chance(self.config.flee_chance, secret);
+                }
             }
             Ok(())
         }
 
         /// Handles enemy's action in `battle` with `hero`.
-        fn enemy_action(&mut self, hero: &mut Hero, battle: &mut Battle) -> Result<()> {
+        fn enemy_action(
+            &mut self,
+            hero: &mut Hero,
+            battle: &mut Battle,
+            secret: Option<&mut SecretRandomness>,
+        ) -> Result<()> {
             let enemy = &mut battle.enemy;
-            let attack_power = self.calculate_attack_power(enemy.strength);
+            let attack_power = self
+                .calculate_attack_power(enemy.strength, secret)
+                .saturating_sub(self.hat_defense(hero.hat_id)?);
             hero.health = hero.health.saturating_sub(attack_power);
             Ok(())
         }
 
-        /// Computes a random number in `range`
-        fn random_in_range(&mut self, range: Range) -> u32 {
+        /// Returns the max health of `hero` at its current level
+        fn hero_max_health(&self, hero: &Hero) -> u32 {
+            self.config
+                .level_growth_table
+                .iter()
+                .take(hero.level as usize - 1)
+                .fold(self.config.hero_max_health, |max_health, level| {
+                    max_health.saturating_add(level.max_health_increase)
+                })
+        }
+
+        /// Raises the level of `hero` while it has enough experience for the next level
+        fn level_up(&mut self, hero_id: AccountId, hero: &mut Hero) {
+            while let Some(next_level) = self
+                .config
+                .level_growth_table
+                .get(hero.level as usize - 1)
+                .copied()
+            {
+                if hero.experience < next_level.experience_required {
+                    break;
+                }
+
+                // increase the level and give the hero the extra health
+                hero.level = hero.level.saturating_add(1);
+                hero.health = hero.health.saturating_add(next_level.max_health_increase);
+
+                // emit event
+                self.env().emit_event(HeroLeveledUp {
+                    hero_id,
+                    level: hero.level,
+                    max_health: self.hero_max_health(hero),
+                });
+            }
+        }
+
+        /// Computes a random number in `range`. It's derived from `secret` if there is one, otherwise from the
+        /// chain.
+        fn random_in_range(&mut self, range: Range, secret: Option<&mut SecretRandomness>) -> u32 {
             // create the subject
             let mut subject = [0_u8; 12];
             subject[0..4].copy_from_slice(&self.random_seed.to_le_bytes());
@@ -691,47 +2800,99 @@ mod game {
             // add to the nonce because we used it
             self.random_nonce += 1;
 
-            // get random hash
-            let (hash, _) = self.env().random(&subject);
+            // get the number from the active source
+            randomness::with_source(secret, |source| source.random_in_range(&subject, range))
+        }
 
-            // create a number from the hash
-            let mut bytes = [0_u8; 4];
-            bytes.copy_from_slice(&hash.as_ref()[0..4]);
-            let random_number = u32::from_le_bytes(bytes);
+        /// Generates a random number between 0 and 99. Returns true if this number is less than `chance`, so
+        /// `chance` is the percentage of chance it returns true.
+        fn random_chance(&mut self, chance: u32, secret: Option<&mut SecretRandomness>) -> bool {
+            self.random_in_range((0, 99).into(), secret) < chance
+        }
 
-            // linearly interpolate the number to the range
-            lerp(range.start, range.end, random_number)
+        /// Returns a commitment to `hash`, recorded in the current block
+        fn commit(&self, hash: [u8; 32]) -> Commitment {
+            Commitment {
+                hash,
+                block_number: self.env().block_number(),
+            }
         }
 
-        /// Generates a random number between 1 and 100. Returns true if this number is less than chance.
-        fn random_chance(&mut self, chance: u32) -> bool {
-            self.random_in_range((0, 100).into()) <= chance
+        /// Checks that `reveal` is the secret `commitment` was made to, in a later block than the commitment.
+        /// Returns the secret and the randomness it decides, which mixes it with the chain randomness of the
+        /// current block. Neither is known when the commitment is made, so the outcome can't be computed ahead of
+        /// time.
+        fn reveal_secret(
+            &self,
+            commitment: Commitment,
+            reveal: Option<[u8; 32]>,
+        ) -> Result<([u8; 32], SecretRandomness)> {
+            if self.env().block_number() <= commitment.block_number {
+                return Err(Error::RevealTooEarly);
+            }
+            let secret = reveal.ok_or(Error::InvalidReveal)?;
+            if self.env().hash_bytes::<Blake2x256>(&secret) != commitment.hash {
+                return Err(Error::InvalidReveal);
+            }
+
+            // mix the secret with the randomness of the current block
+            let (randomness, _) = self.env().random(&secret);
+            let mut bytes = [0_u8; 32];
+            bytes.copy_from_slice(randomness.as_ref());
+            Ok((secret, SecretRandomness::new(bytes)))
         }
 
         /// Calculates attack power for strength, taking into account the config's attack variance.
-        fn calculate_attack_power(&mut self, strength: u32) -> u32 {
+        fn calculate_attack_power(
+            &mut self,
+            strength: u32,
+            secret: Option<&mut SecretRandomness>,
+        ) -> u32 {
             // this is a workaround because random_in_range supports unsigned only
+            let attack_variance = self.config.attack_variance;
             let unsigned_variance =
-                self.random_in_range((0, self.config.attack_variance * 2 + 1).into());
-            let delta = unsigned_variance as i32 - self.config.attack_variance as i32;
-            (strength as i32 + delta) as u32
+                self.random_in_range((0, attack_variance.saturating_mul(2)).into(), secret);
+            let delta = unsigned_variance as i64 - attack_variance as i64;
+            (strength as i64 + delta).clamp(0, u32::MAX as i64) as u32
+        }
+    }
+
+    /// Returns the expected score, in thousandths, of a player whose rating is `rating_difference` higher
+    /// than their opponent's. Approximates `1 / (1 + 10^(-difference / 400))` without floating point.
+    fn elo_expected_score(rating_difference: i64) -> i64 {
+        /// The difference in rating between each entry in `TABLE`
+        const STEP: i64 = 50;
+        /// The expected scores for differences of 0, 50, 100, ... 800
+        const TABLE: [i64; 17] = [
+            500, 571, 640, 703, 760, 808, 849, 882, 909, 930, 947, 960, 969, 977, 983, 987, 990,
+        ];
+
+        // interpolate between the two nearest entries
+        let distance = rating_difference.abs().min(STEP * (TABLE.len() as i64 - 1));
+        let index = (distance / STEP) as usize;
+        let score = match TABLE.get(index + 1) {
+            Some(next) => TABLE[index] + (next - TABLE[index]) * (distance % STEP) / STEP,
+            None => TABLE[index],
+        };
+
+        // the expected scores of both players add up to 1
+        if rating_difference < 0 {
+            1000 - score
+        } else {
+            score
         }
     }
 
-    /// Linearly interpolates between `a` and `b` by `t`, where `t` is considered
-    /// a fraction of its max value
-    fn lerp(a: u32, b: u32, t: u32) -> u32 {
-        const PRECISION: u64 = 100;
-        let input = (t as u64) * PRECISION;
-        let fraction = input / u32::MAX as u64;
-        let length: u64 = b as u64 - a as u64;
-        let output = ((fraction * length) / PRECISION) + a as u64;
-        output as u32
+    /// Returns the amount of rating the winner gains and the loser loses
+    fn elo_rating_delta(winner_rating: u32, loser_rating: u32, k_factor: u32) -> u32 {
+        let expected_score = elo_expected_score(winner_rating as i64 - loser_rating as i64);
+        ((k_factor as i64 * (1000 - expected_score) + 500) / 1000) as u32
     }
 
     #[cfg(test)]
     pub mod tests {
         use super::*;
+        use crate::randomness::{sample_range, ScriptedRandomness, SeededRandomness};
         use efinity_contracts::{mock, AccountId};
         use ink_env::test;
         use std::cell::RefCell;
@@ -839,36 +3000,745 @@ mod game {
             game.mutate_config(mutation.clone()).unwrap();
             let mut config = game.get_config();
 
-            // make sure the potion cost changed
-            assert_eq!(config.potion_cost, 1000);
+            // make sure the potion cost changed
+            assert_eq!(config.potion_cost, 1000);
+
+            // if we change the potion cost back, everything else should be the same
+            config.potion_cost = initial_config.potion_cost;
+            assert_eq!(initial_config, config);
+
+            // an invalid mutation is rejected without changing anything
+            let invalid_mutation = ConfigMutation {
+                potion_cost: Some(1),
+                flee_chance: Some(101),
+                ..Default::default()
+            };
+            assert_eq!(
+                game.mutate_config(invalid_mutation),
+                Err(Error::InvalidConfig {
+                    field: ConfigField::FleeChance,
+                    reason: InvalidConfigReason::ChanceAbove100,
+                })
+            );
+            assert_eq!(game.get_config().potion_cost, 1000);
+
+            // bob cannot change the config
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.mutate_config(mutation), Err(Error::NoPermission));
+        }
+
+        /// Test the config history and `rollback_config` function
+        #[ink::test]
+        fn test_config_history() {
+            let mut game = init_game(Default::default());
+            assert_eq!(game.get_config_version(), 0);
+
+            // each mutation is a new version
+            for potion_cost in 1..=CONFIG_HISTORY_SIZE {
+                game.mutate_config(ConfigMutation {
+                    potion_cost: Some(potion_cost as _),
+                    ..Default::default()
+                })
+                .unwrap();
+            }
+            assert_eq!(game.get_config_version(), CONFIG_HISTORY_SIZE);
+
+            // a mutation that changes nothing is not a new version
+            game.mutate_config(ConfigMutation {
+                potion_cost: Some(CONFIG_HISTORY_SIZE as _),
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(game.get_config_version(), CONFIG_HISTORY_SIZE);
+
+            // the oldest version was removed from the history
+            assert_eq!(game.get_config_history(0), None);
+            assert_eq!(game.rollback_config(0), Err(Error::ConfigVersionNotFound));
+            assert_eq!(game.get_config_history(1).unwrap().potion_cost, 1);
+
+            // bob cannot roll back
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.rollback_config(1), Err(Error::NoPermission));
+
+            // a rollback is recorded as a new version
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.rollback_config(1).unwrap();
+            assert_eq!(game.get_config().potion_cost, 1);
+            assert_eq!(game.get_config_version(), CONFIG_HISTORY_SIZE + 1);
+            assert_eq!(
+                game.get_config_history(CONFIG_HISTORY_SIZE + 1),
+                Some(game.get_config())
+            );
+        }
+
+        /// Test `schedule_config_mutation` and `cancel_config_schedule` functions
+        #[ink::test]
+        fn test_config_schedule() {
+            let mut game = init_game(Default::default());
+            game.create_hero().unwrap();
+            game.mint_gold(100).unwrap();
+            let block = game.env().block_number();
+            let mutation = ConfigMutation {
+                potion_cost: Some(10),
+                ..Default::default()
+            };
+
+            // invalid schedules are rejected
+            assert_eq!(
+                game.schedule_config_mutation(mutation.clone(), block + 2, Some(block + 2)),
+                Err(Error::InvalidSchedule)
+            );
+            assert_eq!(
+                game.schedule_config_mutation(
+                    ConfigMutation {
+                        flee_chance: Some(101),
+                        ..Default::default()
+                    },
+                    block + 2,
+                    None
+                ),
+                Err(Error::InvalidConfig {
+                    field: ConfigField::FleeChance,
+                    reason: InvalidConfigReason::ChanceAbove100,
+                })
+            );
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(
+                game.schedule_config_mutation(mutation.clone(), block + 2, None),
+                Err(Error::NoPermission)
+            );
+
+            // schedule cheaper potions from block + 2 until block + 4
+            test::set_caller::<EfinityEnvironment>(alice());
+            let schedule_id = game
+                .schedule_config_mutation(mutation, block + 2, Some(block + 4))
+                .unwrap();
+            assert_eq!(game.get_config_schedules().len(), 1);
+            assert_eq!(game.get_config().potion_cost, 50);
+
+            // the mutation is applied by the first gameplay message once it's due
+            test::advance_block::<EfinityEnvironment>();
+            test::advance_block::<EfinityEnvironment>();
+            game.buy_potion(1).unwrap();
+            assert_eq!(game.get_gold_balance(alice()), 90);
+            assert_eq!(game.get_config().potion_cost, 10);
+            assert!(game.get_config_schedules()[0].is_active());
+
+            // and reverted once the revert block is reached
+            test::advance_block::<EfinityEnvironment>();
+            test::advance_block::<EfinityEnvironment>();
+            game.buy_potion(1).unwrap();
+            assert_eq!(game.get_gold_balance(alice()), 40);
+            assert_eq!(game.get_config().potion_cost, 50);
+            assert!(game.get_config_schedules().is_empty());
+            assert_eq!(
+                game.cancel_config_schedule(schedule_id),
+                Err(Error::ConfigScheduleNotFound)
+            );
+
+            // cancelling an applied mutation reverts it
+            let schedule_id = game
+                .schedule_config_mutation(
+                    ConfigMutation {
+                        potion_cost: Some(5),
+                        ..Default::default()
+                    },
+                    block,
+                    Some(block + 100),
+                )
+                .unwrap();
+            game.rest().unwrap();
+            assert_eq!(game.get_config().potion_cost, 5);
+            game.cancel_config_schedule(schedule_id).unwrap();
+            assert_eq!(game.get_config().potion_cost, 50);
+            assert!(game.get_config_schedules().is_empty());
+
+            // schedules are kept in the order they are due, up to the limit
+            let mutation = ConfigMutation {
+                potion_cost: Some(5),
+                ..Default::default()
+            };
+            let late_id = game
+                .schedule_config_mutation(mutation.clone(), block + 50, None)
+                .unwrap();
+            let early_id = game
+                .schedule_config_mutation(mutation.clone(), block + 40, Some(block + 60))
+                .unwrap();
+            let schedule_ids = |game: &Game| {
+                game.get_config_schedules()
+                    .iter()
+                    .map(|schedule| schedule.id)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(schedule_ids(&game), vec![early_id, late_id]);
+            for _ in 2..MAX_CONFIG_SCHEDULES {
+                game.schedule_config_mutation(mutation.clone(), block + 100, None)
+                    .unwrap();
+            }
+            assert_eq!(
+                game.schedule_config_mutation(mutation, block + 100, None),
+                Err(Error::TooManyConfigSchedules)
+            );
+
+            // an applied mutation waits for its revert block
+            while game.env().block_number() < block + 50 {
+                test::advance_block::<EfinityEnvironment>();
+            }
+            game.rest().unwrap();
+            assert_eq!(game.get_config_schedules()[0].id, early_id);
+            assert!(game.get_config_schedules()[0].is_active());
+            assert_eq!(game.get_config_schedules().len(), MAX_CONFIG_SCHEDULES - 1);
+        }
+
+        /// Test `Config::changes_to`
+        #[test]
+        fn test_config_changes() {
+            let config = Config::default();
+            assert!(config.changes_to(&config).is_empty());
+
+            let other = Config {
+                potion_cost: 1,
+                hat_defense_range: (0, 0).into(),
+                ..Default::default()
+            };
+            assert_eq!(
+                config.changes_to(&other),
+                vec![
+                    ConfigChange {
+                        field: ConfigField::HatDefenseRange,
+                        old: ConfigValue::Range((1, 4).into()),
+                        new: ConfigValue::Range((0, 0).into()),
+                    },
+                    ConfigChange {
+                        field: ConfigField::PotionCost,
+                        old: ConfigValue::Balance(50),
+                        new: ConfigValue::Balance(1),
+                    },
+                ]
+            );
+        }
+
+        /// Test `Config::validate` with valid and invalid values for every field
+        #[test]
+        fn test_validate_config() {
+            use ConfigField::*;
+            use InvalidConfigReason::*;
+
+            /// The expected result for an invalid value
+            fn invalid(field: ConfigField, reason: InvalidConfigReason) -> Result<()> {
+                Err(Error::InvalidConfig { field, reason })
+            }
+
+            /// `count` valid rarity tiers
+            fn tiers(count: usize) -> Vec<RarityTier> {
+                vec![
+                    RarityTier {
+                        weight: 1,
+                        strength_range: (1, 2).into(),
+                        defense_range: (1, 2).into(),
+                    };
+                    count
+                ]
+            }
+
+            let cases: &[(fn(&mut Config), Result<()>)] = &[
+                (|x| x.hero_max_health = 1, Ok(())),
+                (|x| x.hero_max_health = 0, invalid(HeroMaxHealth, Zero)),
+                (|x| x.starting_weapon_strength_range = (3, 3).into(), Ok(())),
+                (
+                    |x| x.starting_weapon_strength_range = (4, 3).into(),
+                    invalid(StartingWeaponStrengthRange, InvertedRange),
+                ),
+                (
+                    |x| x.purchased_weapon_strength_range = (0, 0).into(),
+                    Ok(()),
+                ),
+                (
+                    |x| x.purchased_weapon_strength_range = (4, 3).into(),
+                    invalid(PurchasedWeaponStrengthRange, InvertedRange),
+                ),
+                (|x| x.hero_initial_potion_count = 0, Ok(())),
+                (|x| x.enemy_health_range = (1, 1).into(), Ok(())),
+                (
+                    |x| x.enemy_health_range = (0, 5).into(),
+                    invalid(EnemyHealthRange, Zero),
+                ),
+                (
+                    |x| x.enemy_health_range = (5, 4).into(),
+                    invalid(EnemyHealthRange, InvertedRange),
+                ),
+                (|x| x.enemy_strength_range = (0, 0).into(), Ok(())),
+                (
+                    |x| x.enemy_strength_range = (2, 1).into(),
+                    invalid(EnemyStrengthRange, InvertedRange),
+                ),
+                (|x| x.enemy_gold_drop_range = (0, 0).into(), Ok(())),
+                (
+                    |x| x.enemy_gold_drop_range = (2, 1).into(),
+                    invalid(EnemyGoldDropRange, InvertedRange),
+                ),
+                (|x| x.attack_variance = u32::MAX, Ok(())),
+                (|x| x.enemy_wearing_hat_chance = 100, Ok(())),
+                (
+                    |x| x.enemy_wearing_hat_chance = 101,
+                    invalid(EnemyWearingHatChance, ChanceAbove100),
+                ),
+                (|x| x.hat_defense_range = (0, 0).into(), Ok(())),
+                (
+                    |x| x.hat_defense_range = (2, 1).into(),
+                    invalid(HatDefenseRange, InvertedRange),
+                ),
+                (|x| x.hero_goes_first_chance = 100, Ok(())),
+                (
+                    |x| x.hero_goes_first_chance = 101,
+                    invalid(HeroGoesFirstChance, ChanceAbove100),
+                ),
+                (|x| x.rest_cost = 0, Ok(())),
+                (|x| x.potion_cost = 0, Ok(())),
+                (|x| x.weapon_cost = 0, Ok(())),
+                (|x| x.flee_chance = 100, Ok(())),
+                (|x| x.flee_chance = 101, invalid(FleeChance, ChanceAbove100)),
+                (|x| x.flee_gold_penalty = 0, Ok(())),
+                (|x| x.flee_health_penalty = 0, Ok(())),
+                (|x| x.experience_per_enemy_health = 0, Ok(())),
+                (|x| x.experience_per_enemy_strength = 0, Ok(())),
+                (|x| x.level_growth_table = Vec::new(), Ok(())),
+                (
+                    |x| x.level_growth_table[1].experience_required = 100,
+                    invalid(LevelGrowthTable, NotIncreasing),
+                ),
+                (|x| x.boss_victory_threshold = 0, Ok(())),
+                (|x| x.boss_stat_multiplier = 1, Ok(())),
+                (
+                    |x| x.boss_stat_multiplier = 0,
+                    invalid(BossStatMultiplier, Zero),
+                ),
+                (|x| x.boss_gold_multiplier = 1, Ok(())),
+                (
+                    |x| x.boss_gold_multiplier = 0,
+                    invalid(BossGoldMultiplier, Zero),
+                ),
+                (|x| x.duel_wager = 0, Ok(())),
+                (|x| x.duel_turn_timeout = 1, Ok(())),
+                (|x| x.duel_turn_timeout = 0, invalid(DuelTurnTimeout, Zero)),
+                (|x| x.pvp_initial_rating = 0, Ok(())),
+                (|x| x.pvp_rating_k_factor = 0, Ok(())),
+                (|x| x.leaderboard_size = 0, Ok(())),
+                (|x| x.leaderboard_size = MAX_LEADERBOARD_SIZE, Ok(())),
+                (
+                    |x| x.leaderboard_size = MAX_LEADERBOARD_SIZE + 1,
+                    invalid(LeaderboardSize, TooManyEntries),
+                ),
+                (|x| x.commit_reveal_enabled = true, Ok(())),
+                (|x| x.season_trophy_count = 0, Ok(())),
+                (
+                    |x| x.season_trophy_count = MAX_LEADERBOARD_SIZE + 1,
+                    invalid(SeasonTrophyCount, TooManyEntries),
+                ),
+                (|x| x.gold_burn_percentage = 100, Ok(())),
+                (
+                    |x| x.gold_burn_percentage = 101,
+                    invalid(GoldBurnPercentage, PercentageAbove100),
+                ),
+                (|x| x.enemy_drops_from_treasury = true, Ok(())),
+                (|x| x.item_sell_base_price = 0, Ok(())),
+                (|x| x.item_sell_price_per_point = 0, Ok(())),
+                (|x| x.item_sales_mint_shortfall = true, Ok(())),
+                (|x| x.forge_cost = 0, Ok(())),
+                (|x| x.forge_strength_percentage = 0, Ok(())),
+                (|x| x.forge_strength_percentage = 100, Ok(())),
+                (
+                    |x| x.forge_strength_percentage = 101,
+                    invalid(ForgeStrengthPercentage, PercentageAbove100),
+                ),
+                (|x| x.forge_bonus_strength_range = (0, 0).into(), Ok(())),
+                (
+                    |x| x.forge_bonus_strength_range = (2, 1).into(),
+                    invalid(ForgeBonusStrengthRange, InvertedRange),
+                ),
+                (|x| x.weapon_upgrade_table = Vec::new(), Ok(())),
+                (
+                    |x| x.weapon_upgrade_table[4].failure_chance = 101,
+                    invalid(WeaponUpgradeTable, ChanceAbove100),
+                ),
+                (|x| x.weapon_durability_range = Some((1, 1).into()), Ok(())),
+                (
+                    |x| x.weapon_durability_range = Some((0, 5).into()),
+                    invalid(WeaponDurabilityRange, Zero),
+                ),
+                (
+                    |x| x.weapon_durability_range = Some((5, 4).into()),
+                    invalid(WeaponDurabilityRange, InvertedRange),
+                ),
+                (|x| x.repair_cost_per_point = 0, Ok(())),
+                (|x| x.broken_weapon_damage_percentage = 0, Ok(())),
+                (
+                    |x| x.broken_weapon_damage_percentage = 101,
+                    invalid(BrokenWeaponDamagePercentage, PercentageAbove100),
+                ),
+                (|x| x.rarity_tiers = tiers(Rarity::COUNT), Ok(())),
+                (
+                    |x| x.rarity_tiers = tiers(Rarity::COUNT + 1),
+                    invalid(RarityTiers, TooManyEntries),
+                ),
+                (
+                    |x| {
+                        x.rarity_tiers = tiers(2);
+                        x.rarity_tiers[1].strength_range = (2, 1).into();
+                    },
+                    invalid(RarityTiers, InvertedRange),
+                ),
+                (
+                    |x| {
+                        x.rarity_tiers = tiers(4);
+                        x.rarity_tiers[3].defense_range = (2, 1).into();
+                    },
+                    invalid(RarityTiers, InvertedRange),
+                ),
+                (|x| x.marketplace_fee_percentage = 100, Ok(())),
+                (
+                    |x| x.marketplace_fee_percentage = 101,
+                    invalid(MarketplaceFeePercentage, PercentageAbove100),
+                ),
+            ];
+
+            for (mutate, expected) in cases {
+                let mut config = Config::default();
+                mutate(&mut config);
+                assert_eq!(&config.validate(), expected);
+            }
+        }
+
+        /// The constructor rejects an invalid config
+        #[ink::test]
+        #[should_panic(expected = "invalid config")]
+        fn test_new_with_invalid_config() {
+            init_game(Config {
+                hero_max_health: 0,
+                ..Default::default()
+            });
+        }
+
+        /// Create an enabled template for tests
+        fn goblin_template() -> EnemyTemplate {
+            EnemyTemplate {
+                id: 7,
+                name: b"Goblin".to_vec(),
+                health_range: (3, 4).into(),
+                strength_range: (1, 2).into(),
+                gold_drop_range: (200, 300).into(),
+                hat_chance: 100,
+                spawn_weight: 10,
+                enabled: true,
+            }
+        }
+
+        /// Test bestiary management functions
+        #[ink::test]
+        fn test_enemy_templates() {
+            let mut game = init_game(Default::default());
+            let mut template = goblin_template();
+
+            // add the template
+            game.add_enemy_template(template.clone()).unwrap();
+            assert_eq!(game.get_enemy_templates(), vec![template.clone()]);
+
+            // cannot add it twice
+            assert_eq!(
+                game.add_enemy_template(template.clone()),
+                Err(Error::EnemyTemplateAlreadyExists)
+            );
+
+            // update the template
+            template.spawn_weight = 20;
+            game.update_enemy_template(template.clone()).unwrap();
+            assert_eq!(game.get_enemy_templates(), vec![template.clone()]);
+
+            // cannot update a template that does not exist
+            let missing_template = EnemyTemplate {
+                id: 8,
+                ..template.clone()
+            };
+            assert_eq!(
+                game.update_enemy_template(missing_template),
+                Err(Error::EnemyTemplateNotFound)
+            );
+
+            // invalid templates are rejected
+            let invalid_templates = [
+                (
+                    EnemyTemplate {
+                        health_range: (0, 5).into(),
+                        ..template.clone()
+                    },
+                    ConfigField::EnemyHealthRange,
+                    InvalidConfigReason::Zero,
+                ),
+                (
+                    EnemyTemplate {
+                        strength_range: (3, 2).into(),
+                        ..template.clone()
+                    },
+                    ConfigField::EnemyStrengthRange,
+                    InvalidConfigReason::InvertedRange,
+                ),
+                (
+                    EnemyTemplate {
+                        gold_drop_range: (3, 2).into(),
+                        ..template.clone()
+                    },
+                    ConfigField::EnemyGoldDropRange,
+                    InvalidConfigReason::InvertedRange,
+                ),
+                (
+                    EnemyTemplate {
+                        hat_chance: 101,
+                        ..template.clone()
+                    },
+                    ConfigField::EnemyWearingHatChance,
+                    InvalidConfigReason::ChanceAbove100,
+                ),
+                (
+                    EnemyTemplate {
+                        spawn_weight: 0,
+                        ..template.clone()
+                    },
+                    ConfigField::EnemySpawnWeight,
+                    InvalidConfigReason::Zero,
+                ),
+            ];
+            for (invalid_template, field, reason) in invalid_templates {
+                let expected = Err(Error::InvalidConfig { field, reason });
+                assert_eq!(
+                    game.update_enemy_template(invalid_template.clone()),
+                    expected
+                );
+                let new_template = EnemyTemplate {
+                    id: 8,
+                    ..invalid_template
+                };
+                assert_eq!(game.add_enemy_template(new_template), expected);
+            }
+            assert_eq!(game.get_enemy_templates(), vec![template.clone()]);
+
+            // disable the template
+            game.disable_enemy_template(template.id).unwrap();
+            assert!(!game.get_enemy_templates()[0].enabled);
+            assert_eq!(
+                game.disable_enemy_template(8),
+                Err(Error::EnemyTemplateNotFound)
+            );
+
+            // bob cannot manage the bestiary
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(
+                game.add_enemy_template(goblin_template()),
+                Err(Error::NoPermission)
+            );
+            assert_eq!(
+                game.update_enemy_template(goblin_template()),
+                Err(Error::NoPermission)
+            );
+            assert_eq!(
+                game.disable_enemy_template(template.id),
+                Err(Error::NoPermission)
+            );
+
+            // the bestiary is limited
+            test::set_caller::<EfinityEnvironment>(alice());
+            for id in 1..MAX_ENEMY_TEMPLATES as EnemyTypeId {
+                game.add_enemy_template(EnemyTemplate {
+                    id: 100 + id,
+                    ..goblin_template()
+                })
+                .unwrap();
+            }
+            assert_eq!(
+                game.add_enemy_template(EnemyTemplate {
+                    id: 8,
+                    ..goblin_template()
+                }),
+                Err(Error::TooManyEnemyTemplates)
+            );
+        }
+
+        /// Test `start_battle` with an enemy template
+        #[ink::test]
+        fn test_start_battle_with_template() {
+            let mut game = init_game(Config {
+                enemy_wearing_hat_chance: 0,
+                ..Default::default()
+            });
+            let template = goblin_template();
+            game.add_enemy_template(template.clone()).unwrap();
+            game.create_hero().unwrap();
+
+            // the enemy is generated from the template
+            game.start_battle(None).unwrap();
+            let enemy = game.heroes.get(alice()).unwrap().battle.unwrap().enemy;
+            assert_eq!(enemy.template_id, Some(template.id));
+            assert!(template.health_range.contains(enemy.health));
+            assert!(template.strength_range.contains(enemy.strength));
+            assert_eq!(enemy.gold_drop_range, template.gold_drop_range);
+            assert!(enemy.hat_id.is_some());
+
+            // once disabled, the enemy is generated from the config
+            game.disable_enemy_template(template.id).unwrap();
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.create_hero().unwrap();
+            game.start_battle(None).unwrap();
+            let enemy = game.heroes.get(bob()).unwrap().battle.unwrap().enemy;
+            assert_eq!(enemy.template_id, None);
+            assert!(enemy.hat_id.is_none());
+        }
+
+        /// Test role functions
+        #[ink::test]
+        fn test_roles() {
+            let mut game = init_game(Default::default());
+            let charlie = accounts().charlie;
+            let mutation = ConfigMutation {
+                potion_cost: Some(1000),
+                ..Default::default()
+            };
+
+            // the owner has every role
+            assert!(game.has_role(alice(), Role::Admin));
+            assert!(game.has_role(alice(), Role::Treasurer));
+            assert!(!game.has_role(bob(), Role::ConfigManager));
+
+            // bob can change the config once he's a config manager
+            game.grant_role(bob(), Role::ConfigManager).unwrap();
+            assert!(game.has_role(bob(), Role::ConfigManager));
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.mutate_config(mutation.clone()).unwrap();
+
+            // but he can't grant roles
+            assert_eq!(
+                game.grant_role(charlie, Role::ConfigManager),
+                Err(Error::NoPermission)
+            );
+            assert_eq!(
+                game.revoke_role(bob(), Role::ConfigManager),
+                Err(Error::NoPermission)
+            );
+
+            // charlie becomes an admin and revokes bob's role
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.grant_role(charlie, Role::Admin).unwrap();
+            test::set_caller::<EfinityEnvironment>(charlie);
+            game.revoke_role(bob(), Role::ConfigManager).unwrap();
+            assert!(!game.has_role(bob(), Role::ConfigManager));
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.mutate_config(mutation), Err(Error::NoPermission));
+
+            // charlie renounces his role
+            test::set_caller::<EfinityEnvironment>(charlie);
+            game.renounce_role(Role::Admin).unwrap();
+            assert!(!game.has_role(charlie, Role::Admin));
+            assert_eq!(
+                game.grant_role(bob(), Role::Pauser),
+                Err(Error::NoPermission)
+            );
+        }
+
+        /// Test `propose_owner` and `accept_ownership` functions
+        #[ink::test]
+        fn test_ownership_transfer() {
+            let mut game = init_game(Default::default());
+            let charlie = accounts().charlie;
+
+            // alice is the owner
+            assert_eq!(game.get_owner(), alice());
+
+            // nothing to accept or cancel yet
+            assert_eq!(game.accept_ownership(), Err(Error::NoPendingOwner));
+            assert_eq!(game.cancel_ownership_proposal(), Err(Error::NoPendingOwner));
+
+            // alice proposes bob. She's still the owner until he accepts.
+            game.propose_owner(bob()).unwrap();
+            assert_eq!(game.get_pending_owner(), Some(bob()));
+            assert_eq!(game.get_owner(), alice());
+
+            // charlie cannot accept for bob
+            test::set_caller::<EfinityEnvironment>(charlie);
+            assert_eq!(game.accept_ownership(), Err(Error::NoPermission));
+            assert_eq!(game.propose_owner(charlie), Err(Error::NoPermission));
 
-            // if we change the potion cost back, everything else should be the same
-            config.potion_cost = initial_config.potion_cost;
-            assert_eq!(initial_config, config);
+            // bob accepts. Now he's the owner.
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.accept_ownership().unwrap();
+            assert_eq!(game.get_owner(), bob());
+            assert_eq!(game.get_pending_owner(), None);
 
-            // bob cannot change the config
+            // alice cannot propose owners any more
+            test::set_caller::<EfinityEnvironment>(alice());
+            assert_eq!(game.propose_owner(alice()), Err(Error::NoPermission));
+
+            // bob proposes charlie, then cancels
             test::set_caller::<EfinityEnvironment>(bob());
-            assert_eq!(game.mutate_config(mutation), Err(Error::NoPermission));
+            game.propose_owner(charlie).unwrap();
+            test::set_caller::<EfinityEnvironment>(alice());
+            assert_eq!(game.cancel_ownership_proposal(), Err(Error::NoPermission));
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.cancel_ownership_proposal().unwrap();
+
+            // charlie can no longer accept
+            test::set_caller::<EfinityEnvironment>(charlie);
+            assert_eq!(game.accept_ownership(), Err(Error::NoPendingOwner));
+            assert_eq!(game.get_owner(), bob());
         }
 
-        /// Test `transfer_contract_ownership` function
+        /// Test `pause` and `unpause` functions
         #[ink::test]
-        fn test_transfer_contract_ownership() {
-            let initial_config = Config::default();
-            let mut game = init_game(initial_config.clone());
-
-            // alice is the owner
-            assert_eq!(game.owner, alice());
+        fn test_pause() {
+            let mut game = init_game(Default::default());
+            game.create_hero().unwrap();
+            game.mint_gold(1000).unwrap();
 
-            // alice transfers to bob. Now he's the owner.
-            game.transfer_contract_ownership(bob()).unwrap();
-            assert_eq!(game.owner, bob());
+            // bob can't pause until he's a pauser
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.pause(Feature::All), Err(Error::NoPermission));
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.grant_role(bob(), Role::Pauser).unwrap();
+            test::set_caller::<EfinityEnvironment>(bob());
 
-            // alice cannot transfer ownership any more
+            // pausing the shop doesn't block battles
+            game.pause(Feature::Shop).unwrap();
+            assert!(game.is_paused(Feature::Shop));
+            assert!(!game.is_paused(Feature::Battles));
+            test::set_caller::<EfinityEnvironment>(alice());
+            assert_eq!(game.buy_potion(1), Err(Error::GamePaused));
+            assert_eq!(game.buy_weapon(), Err(Error::GamePaused));
+            assert_eq!(game.rest(), Err(Error::GamePaused));
+            game.start_battle(None).unwrap();
+
+            // pausing everything blocks every gameplay message
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.unpause(Feature::Shop).unwrap();
+            game.pause(Feature::All).unwrap();
+            assert!(game.is_paused(Feature::Shop));
+            test::set_caller::<EfinityEnvironment>(alice());
+            let weapon_id = game.get_hero(alice()).unwrap().hero.weapon_id;
             assert_eq!(
-                game.transfer_contract_ownership(alice()),
-                Err(Error::NoPermission)
+                game.advance_battle(Command::Attack, None),
+                Err(Error::GamePaused)
             );
+            assert_eq!(game.equip(weapon_id), Err(Error::GamePaused));
+            assert_eq!(game.buy_potion(1), Err(Error::GamePaused));
+            assert_eq!(game.challenge(bob()), Err(Error::GamePaused));
+            test::set_caller::<EfinityEnvironment>(accounts().charlie);
+            assert_eq!(game.create_hero(), Err(Error::GamePaused));
+
+            // read-only messages keep working
+            assert!(game.get_hero(alice()).unwrap().hero.battle.is_some());
+            assert_eq!(game.get_config(), Config::default());
+
+            // play resumes once unpaused
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.unpause(Feature::All).unwrap();
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.advance_battle(Command::Attack, None).unwrap();
         }
 
         /// Test `start_battle` function
@@ -883,18 +3753,22 @@ mod game {
             let mut game = init_game(config.clone());
 
             // starting a battle without a hero fails
-            assert_eq!(game.start_battle().unwrap_err(), Error::HeroNotFound);
+            assert_eq!(game.start_battle(None).unwrap_err(), Error::HeroNotFound);
 
             // create the hero and then start the battle
             game.create_hero().unwrap();
-            game.start_battle().unwrap();
-            let hero = game.get_hero(alice()).unwrap();
+            game.start_battle(None).unwrap();
+            let hero = game.get_hero(alice()).unwrap().hero;
             let enemy = hero.battle.unwrap().enemy;
 
             // enemy should be wearing a hat
             let hat_id = WrappedTokenId(enemy.hat_id.unwrap());
             assert_eq!(hat_id.token_type(), Some(TokenType::Hat));
 
+            // the hat's defense should be in the correct range
+            let hat_metadata = game.get_metadata(hat_id.0).unwrap().unwrap();
+            assert!(config.hat_defense_range.contains(hat_metadata.defense));
+
             // the enemy stats should be in the correct ranges
             assert!(config.enemy_health_range.contains(enemy.health));
             assert!(config.enemy_strength_range.contains(enemy.strength));
@@ -909,10 +3783,10 @@ mod game {
             // bob starts a battle
             test::set_caller::<EfinityEnvironment>(bob());
             game.create_hero().unwrap();
-            game.start_battle().unwrap();
+            game.start_battle(None).unwrap();
 
             // ensure the enemy has no hat
-            let hero = game.get_hero(bob()).unwrap();
+            let hero = game.get_hero(bob()).unwrap().hero;
             let enemy = hero.battle.unwrap().enemy;
             assert!(enemy.hat_id.is_none());
         }
@@ -925,54 +3799,50 @@ mod game {
                 hero_initial_potion_count: 0,
                 hero_max_health: 100,
                 enemy_health_range: (100, 100).into(),
+                attack_variance: 2,
                 ..Default::default()
             };
-            let attack_variance = config.attack_variance;
             let mut game = init_game(config);
+            randomness::set_source(ScriptedRandomness::new([
+                8,  // weapon strength
+                99, // no hat
+                100, 10, // enemy health and strength
+                0, 3, 0, // hero goes first, hero deals 9 damage, enemy deals 8 damage
+                0, // hero goes first and fails to heal
+                99, 2, // enemy goes first and deals 10 damage
+            ]));
             game.create_hero().unwrap();
-            game.start_battle().unwrap();
-            let initial_enemy = game.get_hero(alice()).unwrap().battle.unwrap().enemy;
+            game.start_battle(None).unwrap();
+            let initial_enemy = game.get_hero(alice()).unwrap().hero.battle.unwrap().enemy;
+            assert_eq!(initial_enemy.health, 100);
+            assert_eq!(initial_enemy.strength, 10);
 
             // make sure attack works
-            game.advance_battle(Command::Attack).unwrap();
-            let hero = game.get_hero(alice()).unwrap();
-            let hero_strength = game.get_metadata(hero.weapon_id).unwrap().unwrap().strength;
+            game.advance_battle(Command::Attack, None).unwrap();
+            let hero = game.get_hero(alice()).unwrap().hero;
             let battle = hero.battle.unwrap();
-
-            // check hero health
-            let expected_hero_health = Range::new(
-                game.config.hero_max_health - initial_enemy.strength - attack_variance,
-                game.config.hero_max_health - initial_enemy.strength + attack_variance,
-            );
-            assert!(expected_hero_health.contains(hero.health));
-
-            // check enemy health
-            let expected_enemy_health = Range::new(
-                initial_enemy.health - hero_strength - attack_variance,
-                initial_enemy.health - hero_strength + attack_variance,
-            );
-            assert!(expected_enemy_health.contains(battle.enemy.health));
+            assert_eq!(hero.health, 92);
+            assert_eq!(battle.enemy.health, 91);
             assert_eq!(battle.round_number, 1);
 
             // trying to heal without potion fails
             assert_eq!(
-                game.advance_battle(Command::Heal).unwrap_err(),
+                game.advance_battle(Command::Heal, None).unwrap_err(),
                 Error::HeroHasNoPotions
             );
 
             // give the hero a potion
-            let mut hero = game.get_hero(alice()).unwrap();
-            let enemy_health = hero.battle.unwrap().enemy.health;
+            let mut hero = game.get_hero(alice()).unwrap().hero;
             hero.health = 50;
             hero.potion_count = 1;
             game.heroes.insert(alice(), &hero);
 
-            // now healing works
-            game.advance_battle(Command::Heal).unwrap();
-            let hero = game.get_hero(alice()).unwrap();
-            assert!(hero.health > 50);
+            // now healing works after the enemy attacks
+            game.advance_battle(Command::Heal, None).unwrap();
+            let hero = game.get_hero(alice()).unwrap().hero;
+            assert_eq!(hero.health, 100);
             assert_eq!(hero.potion_count, 0);
-            assert_eq!(hero.battle.unwrap().enemy.health, enemy_health);
+            assert_eq!(hero.battle.unwrap().enemy.health, 91);
         }
 
         /// Test `advance_battle` where the hero wins
@@ -981,20 +3851,27 @@ mod game {
             let config = Config {
                 enemy_health_range: (1, 1).into(),
                 enemy_wearing_hat_chance: 100,
+                hat_defense_range: (0, 0).into(),
+                attack_variance: 2,
+                enemy_gold_drop_range: (20, 50).into(),
                 ..Default::default()
             };
             let mut game = init_game(config);
+            randomness::set_source(ScriptedRandomness::new([
+                7, // weapon strength
+                0, 0, // hat with no defense
+                1, 5, // enemy health and strength
+                0, 2,  // hero goes first and deals 7 damage
+                30, // gold reward
+            ]));
             let caller = bob();
             test::set_caller::<EfinityEnvironment>(caller);
 
             game.create_hero().unwrap();
-            game.start_battle().unwrap();
-
-            // set hero health to 1 less than max health
-            let mut hero = game.get_hero(caller).unwrap();
-            hero.health = game.config.hero_max_health - 1;
+            game.start_battle(None).unwrap();
 
             // verify the enemy's hat is owned by the contract
+            let hero = game.get_hero(caller).unwrap().hero;
             let battle = hero.battle.unwrap();
             let hat_id = battle.enemy.hat_id.unwrap();
             // the contract should own the hat
@@ -1008,15 +3885,16 @@ mod game {
             );
 
             // defeat the enemy
-            game.advance_battle(Command::Attack).unwrap();
+            game.advance_battle(Command::Attack, None).unwrap();
 
-            let hero = game.get_hero(caller).unwrap();
+            let hero = game.get_hero(caller).unwrap().hero;
+            assert!(hero.battle.is_none());
+            assert_eq!(hero.health, game.config.hero_max_health);
             assert_eq!(hero.consecutive_victory_count, 1);
             assert_eq!(hero.highest_consecutive_victory_count, 1);
 
             // make sure the correct amount of gold is received
-            let gold_amount = game.get_gold_balance(caller);
-            assert!(game.config.enemy_gold_drop_range.contains(gold_amount as _));
+            assert_eq!(game.get_gold_balance(caller), 30);
 
             // the hat should now be owned by the hero
             assert_ne!(game.env().account_id(), caller);
@@ -1033,13 +3911,23 @@ mod game {
         fn test_lose_battle() {
             let mut game = init_game(Config {
                 enemy_wearing_hat_chance: 100,
+                hat_defense_range: (1, 4).into(),
+                enemy_health_range: (30, 60).into(),
+                enemy_strength_range: (5, 15).into(),
+                attack_variance: 2,
                 ..Default::default()
             });
+            randomness::set_source(ScriptedRandomness::new([
+                7, // weapon strength
+                0, 2, // hat with 2 defense
+                40, 10, // enemy health and strength
+                99, 2, // enemy goes first and deals 10 damage
+            ]));
             game.create_hero().unwrap();
-            game.start_battle().unwrap();
+            game.start_battle(None).unwrap();
 
             // set hero health to 1 and increase victory count
-            let mut hero = game.get_hero(alice()).unwrap();
+            let mut hero = game.get_hero(alice()).unwrap().hero;
             hero.health = 1;
             hero.consecutive_victory_count = 5;
             game.heroes.insert(alice(), &hero);
@@ -1047,32 +3935,609 @@ mod game {
             // the hat token exists
             let hat_id = hero.battle.unwrap().enemy.hat_id.unwrap();
             assert_eq!(
-                game.env().extension().balance_of(
-                    game.collection_id,
-                    hat_id,
-                    game.env().account_id()
-                ),
-                1
+                game.env().extension().balance_of(
+                    game.collection_id,
+                    hat_id,
+                    game.env().account_id()
+                ),
+                1
+            );
+
+            // lose the battle
+            game.advance_battle(Command::Attack, None).unwrap();
+            let hero = game.get_hero(alice()).unwrap().hero;
+            assert!(hero.battle.is_none());
+
+            // health and victory count should be reset
+            assert_eq!(hero.health, game.config.hero_max_health);
+            assert_eq!(hero.consecutive_victory_count, 0);
+
+            // the hat token was burned
+            assert_eq!(
+                game.env().extension().balance_of(
+                    game.collection_id,
+                    hat_id,
+                    game.env().account_id()
+                ),
+                0
+            );
+        }
+
+        /// Test `advance_battle` where the hero flees
+        #[ink::test]
+        fn test_flee_battle() {
+            let config = Config {
+                enemy_wearing_hat_chance: 100,
+                flee_chance: 100,
+                flee_gold_penalty: 10,
+                flee_health_penalty: 5,
+                ..Default::default()
+            };
+            let mut game = init_game(config.clone());
+            game.create_hero().unwrap();
+            game.mint_gold(15).unwrap();
+            game.start_battle(None).unwrap();
+
+            // increase victory count
+            let mut hero = game.get_hero(alice()).unwrap().hero;
+            hero.consecutive_victory_count = 3;
+            game.heroes.insert(alice(), &hero);
+            let hat_id = hero.battle.unwrap().enemy.hat_id.unwrap();
+
+            // flee the battle
+            game.advance_battle(Command::Flee, None).unwrap();
+            let hero = game.get_hero(alice()).unwrap().hero;
+            assert!(hero.battle.is_none());
+
+            // victory count is untouched and the penalties were applied
+            assert_eq!(hero.consecutive_victory_count, 3);
+            assert_eq!(game.get_gold_balance(alice()), 5);
+            assert!(hero.health <= config.hero_max_health - config.flee_health_penalty);
+            assert!(hero.health > 0);
+
+            // the hat token was burned
+            assert_eq!(
+                game.env().extension().balance_of(
+                    game.collection_id,
+                    hat_id,
+                    game.env().account_id()
+                ),
+                0
+            );
+        }
+
+        /// Test that hat defense reduces damage in battle
+        #[ink::test]
+        fn test_hat_defense() {
+            let config = Config {
+                hero_max_health: 100,
+                enemy_health_range: (100, 100).into(),
+                enemy_strength_range: (10, 10).into(),
+                enemy_wearing_hat_chance: 100,
+                hat_defense_range: (100, 100).into(),
+                attack_variance: 0,
+                ..Default::default()
+            };
+            let mut game = init_game(config);
+            game.create_hero().unwrap();
+
+            // equip a hat with 3 defense
+            let hat_id = game.mint_nft(alice(), TokenType::Hat, false, None).unwrap();
+            game.add_hat_attribute(hat_id, (3, 3).into(), None).unwrap();
+            game.equip(hat_id).unwrap();
+
+            // fight a round
+            game.start_battle(None).unwrap();
+            game.advance_battle(Command::Attack, None).unwrap();
+            let hero = game.get_hero(alice()).unwrap().hero;
+
+            // the hero's hat blocked some of the damage
+            assert_eq!(hero.health, 93);
+
+            // the enemy's hat blocked all of the damage
+            assert_eq!(hero.battle.unwrap().enemy.health, 100);
+        }
+
+        /// Test metadata stored before it was versioned
+        #[ink::test]
+        fn test_unversioned_metadata() {
+            let mut game = init_game(Config {
+                attack_variance: 0,
+                enemy_health_range: (100, 100).into(),
+                enemy_strength_range: (0, 0).into(),
+                enemy_wearing_hat_chance: 0,
+                ..Default::default()
+            });
+            let weapon_id = game.create_hero().unwrap().weapon_id;
+
+            // store the weapon's metadata the way it was stored before, as only the strength
+            game.env()
+                .extension()
+                .set_attribute(
+                    game.collection_id,
+                    Some(weapon_id),
+                    attribute_key(),
+                    scale::Encode::encode(&7_u32),
+                )
+                .unwrap();
+            let metadata = game.get_metadata(weapon_id).unwrap().unwrap();
+            assert_eq!(metadata.strength, 7);
+            assert_eq!(metadata.defense, 0);
+            assert!(metadata.durability.is_none());
+
+            // the weapon can still be used in battle
+            game.start_battle(None).unwrap();
+            game.advance_battle(Command::Attack, None).unwrap();
+            let hero = game.get_hero(alice()).unwrap().hero;
+            assert_eq!(hero.battle.unwrap().enemy.health, 93);
+
+            // unknown layouts fail to decode
+            game.env()
+                .extension()
+                .set_attribute(
+                    game.collection_id,
+                    Some(weapon_id),
+                    attribute_key(),
+                    vec![TokenMetadata::VERSION + 1, 0, 0, 0, 0, 0],
+                )
+                .unwrap();
+            assert!(matches!(
+                game.get_metadata(weapon_id),
+                Err(Error::AttributeDecodeFailed)
+            ));
+        }
+
+        /// Test leveling up after winning battles
+        #[ink::test]
+        fn test_level_up() {
+            let config = Config {
+                enemy_health_range: (15, 15).into(),
+                enemy_wearing_hat_chance: 0,
+                experience_per_enemy_health: 1,
+                experience_per_enemy_strength: 0,
+                level_growth_table: vec![
+                    LevelGrowth {
+                        experience_required: 10,
+                        max_health_increase: 5,
+                    },
+                    LevelGrowth {
+                        experience_required: 20,
+                        max_health_increase: 10,
+                    },
+                ],
+                ..Default::default()
+            };
+            let mut game = init_game(config.clone());
+            game.create_hero().unwrap();
+
+            /// Starts a battle and wins it in one round
+            fn win_battle(game: &mut Game) {
+                game.start_battle(None).unwrap();
+                let mut hero = game.heroes.get(alice()).unwrap();
+                let mut battle = hero.battle.unwrap();
+                assert_eq!(battle.enemy.experience, 15);
+                battle.enemy.health = 1;
+                hero.battle = Some(battle);
+                game.heroes.insert(alice(), &hero);
+                game.advance_battle(Command::Attack, None).unwrap();
+            }
+
+            // a new hero is level 1
+            let info = game.get_hero(alice()).unwrap();
+            assert_eq!(info.hero.level, 1);
+            assert_eq!(info.max_health, config.hero_max_health);
+            assert_eq!(info.next_level_experience, Some(10));
+
+            // the first victory reaches level 2
+            win_battle(&mut game);
+            let info = game.get_hero(alice()).unwrap();
+            assert_eq!(info.hero.experience, 15);
+            assert_eq!(info.hero.level, 2);
+            assert_eq!(info.max_health, config.hero_max_health + 5);
+            assert_eq!(info.next_level_experience, Some(20));
+
+            // the second victory reaches the max level
+            win_battle(&mut game);
+            let info = game.get_hero(alice()).unwrap();
+            assert_eq!(info.hero.experience, 30);
+            assert_eq!(info.hero.level, 3);
+            assert_eq!(info.max_health, config.hero_max_health + 15);
+            assert_eq!(info.next_level_experience, None);
+        }
+
+        /// Test `advance_battle` against a boss
+        #[ink::test]
+        fn test_boss_battle() {
+            let config = Config {
+                enemy_health_range: (10, 10).into(),
+                enemy_strength_range: (2, 2).into(),
+                enemy_gold_drop_range: (10, 10).into(),
+                enemy_wearing_hat_chance: 0,
+                hat_defense_range: (0, 0).into(),
+                boss_victory_threshold: 2,
+                boss_stat_multiplier: 3,
+                boss_gold_multiplier: 5,
+                ..Default::default()
+            };
+            let mut game = init_game(config);
+            game.create_hero().unwrap();
+
+            // reach the threshold
+            let mut hero = game.heroes.get(alice()).unwrap();
+            hero.consecutive_victory_count = 2;
+            game.heroes.insert(alice(), &hero);
+
+            // the enemy is a boss with multiplied stats and a hat
+            game.start_battle(None).unwrap();
+            let mut hero = game.heroes.get(alice()).unwrap();
+            let mut battle = hero.battle.unwrap();
+            assert!(battle.enemy.is_boss);
+            assert_eq!(battle.enemy.health, 30);
+            assert_eq!(battle.enemy.strength, 6);
+            assert_eq!(battle.enemy.gold_drop_range, Range::new(50, 50));
+            let hat_id = battle.enemy.hat_id.unwrap();
+
+            // defeat the boss
+            battle.enemy.health = 1;
+            hero.battle = Some(battle);
+            game.heroes.insert(alice(), &hero);
+            game.advance_battle(Command::Attack, None).unwrap();
+            assert_eq!(game.get_gold_balance(alice()), 50);
+
+            // the hero received the trophy, which was minted after the hat
+            let trophy_id =
+                WrappedTokenId::new(WrappedTokenId(hat_id).id() + 1, Some(TokenType::Trophy)).0;
+            assert_eq!(
+                game.env()
+                    .extension()
+                    .balance_of(game.collection_id, trophy_id, alice()),
+                1
+            );
+
+            // trophies cannot be equipped
+            assert_eq!(game.equip(trophy_id), Err(Error::InvalidEquipment));
+
+            // the next enemy is not a boss
+            game.start_battle(None).unwrap();
+            let hero = game.heroes.get(alice()).unwrap();
+            assert!(!hero.battle.unwrap().enemy.is_boss);
+        }
+
+        /// Test `challenge` function
+        #[ink::test]
+        fn test_challenge() {
+            let mut game = init_game(Config {
+                duel_wager: 10,
+                ..Default::default()
+            });
+
+            // cannot challenge without a hero
+            assert_eq!(game.challenge(bob()), Err(Error::HeroNotFound));
+
+            // cannot challenge self or an account without a hero
+            game.create_hero().unwrap();
+            assert_eq!(game.challenge(alice()), Err(Error::CannotDuelSelf));
+            assert_eq!(game.challenge(bob()), Err(Error::HeroNotFound));
+
+            // cannot challenge without enough gold for the wager
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.create_hero().unwrap();
+            test::set_caller::<EfinityEnvironment>(alice());
+            assert_eq!(game.challenge(bob()), Err(Error::NotEnoughGold));
+
+            // challenge bob. The wager is held by the contract.
+            game.mint_gold(10).unwrap();
+            let duel_id = game.challenge(bob()).unwrap();
+            assert_eq!(game.get_gold_balance(alice()), 0);
+            assert_eq!(game.get_gold_balance(game.env().account_id()), 10);
+            let duel = game.get_duel(duel_id).unwrap();
+            assert_eq!(duel.challenger, alice());
+            assert_eq!(duel.opponent, bob());
+            assert!(!duel.accepted);
+
+            // alice is locked out of other activities, but bob is free until he accepts
+            assert_eq!(game.start_battle(None), Err(Error::HeroIsInBattle));
+            assert_eq!(game.rest(), Err(Error::HeroIsInBattle));
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert!(game.get_hero(bob()).unwrap().hero.duel_id.is_none());
+            game.start_battle(None).unwrap();
+
+            // bob can't accept while in a battle
+            assert_eq!(game.accept_duel(duel_id), Err(Error::HeroIsInBattle));
+            assert_eq!(game.accept_duel(duel_id + 1), Err(Error::DuelNotFound));
+
+            // charlie cannot challenge either of them
+            test::set_caller::<EfinityEnvironment>(accounts().charlie);
+            game.create_hero().unwrap();
+            assert_eq!(game.challenge(alice()), Err(Error::OpponentIsInBattle));
+            assert_eq!(game.challenge(bob()), Err(Error::OpponentIsInBattle));
+        }
+
+        /// Test `decline_duel` function
+        #[ink::test]
+        fn test_decline_duel() {
+            let mut game = init_game(Config {
+                duel_wager: 10,
+                ..Default::default()
+            });
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.create_hero().unwrap();
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.create_hero().unwrap();
+            game.mint_gold(10).unwrap();
+            let duel_id = game.challenge(bob()).unwrap();
+
+            // alice cannot accept her own challenge, and charlie can't decline it
+            assert_eq!(game.accept_duel(duel_id), Err(Error::NoPermission));
+            test::set_caller::<EfinityEnvironment>(accounts().charlie);
+            assert_eq!(game.decline_duel(duel_id), Err(Error::NoPermission));
+
+            // bob declines, even while battles are paused
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.pause(Feature::Battles).unwrap();
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.accept_duel(duel_id), Err(Error::GamePaused));
+            game.decline_duel(duel_id).unwrap();
+            assert!(game.get_duel(duel_id).is_none());
+
+            // the wager was returned and alice was released
+            assert_eq!(game.get_gold_balance(alice()), 10);
+            assert!(game.heroes.get(alice()).unwrap().duel_id.is_none());
+            assert!(game.heroes.get(bob()).unwrap().duel_id.is_none());
+            assert_eq!(game.decline_duel(duel_id), Err(Error::DuelNotFound));
+        }
+
+        /// Test `advance_duel` function
+        #[ink::test]
+        fn test_advance_duel() {
+            let mut game = init_game(Config {
+                duel_wager: 10,
+                weapon_durability_range: Some((5, 5).into()),
+                ..Default::default()
+            });
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.create_hero().unwrap();
+            game.mint_gold(10).unwrap();
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.create_hero().unwrap();
+            game.mint_gold(10).unwrap();
+            let duel_id = game.challenge(bob()).unwrap();
+
+            // cannot fight before the duel is accepted
+            assert_eq!(
+                game.advance_duel(Command::Attack),
+                Err(Error::DuelNotAccepted)
+            );
+
+            // bob accepts
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.accept_duel(duel_id).unwrap();
+            assert_eq!(game.accept_duel(duel_id), Err(Error::DuelAlreadyAccepted));
+            assert_eq!(game.decline_duel(duel_id), Err(Error::DuelAlreadyAccepted));
+            assert_eq!(game.get_gold_balance(bob()), 0);
+            let duel = game.get_duel(duel_id).unwrap();
+            assert!(duel.accepted);
+
+            // the hero without the turn cannot act
+            let first = duel.turn;
+            let second = duel.other(first);
+            test::set_caller::<EfinityEnvironment>(second);
+            assert_eq!(game.advance_duel(Command::Attack), Err(Error::NotYourTurn));
+
+            // the first hero attacks, wearing their weapon, then it's the second hero's turn
+            test::set_caller::<EfinityEnvironment>(first);
+            game.advance_duel(Command::Attack).unwrap();
+            let second_hero = game.heroes.get(second).unwrap();
+            assert!(second_hero.health < game.config.hero_max_health);
+            assert_eq!(game.get_duel(duel_id).unwrap().turn, second);
+            let first_weapon_id = game.heroes.get(first).unwrap().weapon_id;
+            let durability = game
+                .get_metadata(first_weapon_id)
+                .unwrap()
+                .unwrap()
+                .durability
+                .unwrap();
+            assert_eq!(durability.current, 4);
+
+            // the second hero attacks with 1 health left
+            let mut first_hero = game.heroes.get(first).unwrap();
+            first_hero.health = 1;
+            game.heroes.insert(first, &first_hero);
+            test::set_caller::<EfinityEnvironment>(second);
+            game.advance_duel(Command::Attack).unwrap();
+
+            // the second hero wins both wagers and the loser recovers
+            assert!(game.get_duel(duel_id).is_none());
+            assert_eq!(game.heroes.get(second).unwrap().rating, 1216);
+            assert_eq!(game.heroes.get(first).unwrap().rating, 1184);
+            assert_eq!(
+                game.get_leaderboard(0, 10),
+                vec![
+                    LeaderboardEntry {
+                        hero_id: second,
+                        rating: 1216
+                    },
+                    LeaderboardEntry {
+                        hero_id: first,
+                        rating: 1184
+                    },
+                ]
+            );
+            assert_eq!(game.get_gold_balance(second), 20);
+            assert_eq!(game.get_gold_balance(first), 0);
+            let first_hero = game.heroes.get(first).unwrap();
+            assert_eq!(first_hero.health, game.config.hero_max_health);
+            assert!(first_hero.duel_id.is_none());
+            assert!(game.heroes.get(second).unwrap().duel_id.is_none());
+        }
+
+        /// Test `advance_duel` when a hero surrenders
+        #[ink::test]
+        fn test_surrender_duel() {
+            let mut game = init_game(Config {
+                duel_wager: 0,
+                ..Default::default()
+            });
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.create_hero().unwrap();
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.create_hero().unwrap();
+            let duel_id = game.challenge(bob()).unwrap();
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.accept_duel(duel_id).unwrap();
+
+            // the hero whose turn it is surrenders
+            let duel = game.get_duel(duel_id).unwrap();
+            test::set_caller::<EfinityEnvironment>(duel.turn);
+            game.advance_duel(Command::Flee).unwrap();
+            assert!(game.get_duel(duel_id).is_none());
+
+            // both heroes can fight again
+            game.start_battle(None).unwrap();
+            test::set_caller::<EfinityEnvironment>(duel.other(duel.turn));
+            game.start_battle(None).unwrap();
+        }
+
+        /// Test `claim_duel_forfeit` function
+        #[ink::test]
+        fn test_duel_forfeit() {
+            let mut game = init_game(Config {
+                duel_wager: 10,
+                duel_turn_timeout: 2,
+                ..Default::default()
+            });
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.create_hero().unwrap();
+            game.mint_gold(10).unwrap();
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.create_hero().unwrap();
+            game.mint_gold(10).unwrap();
+            let duel_id = game.challenge(bob()).unwrap();
+
+            // a hero can't be created again to leave the duel
+            assert_eq!(game.create_hero(), Err(Error::HeroAlreadyExists));
+
+            // there is no deadline until the duel is accepted
+            assert_eq!(game.claim_duel_forfeit(), Err(Error::DuelNotAccepted));
+            test::set_caller::<EfinityEnvironment>(bob());
+            game.accept_duel(duel_id).unwrap();
+            let duel = game.get_duel(duel_id).unwrap();
+            let (first, second) = (duel.turn, duel.other(duel.turn));
+            assert_eq!(duel.turn_deadline, Some(game.env().block_number() + 2));
+
+            // taking a turn resets the deadline for the other hero
+            test::advance_block::<EfinityEnvironment>();
+            test::set_caller::<EfinityEnvironment>(first);
+            game.advance_duel(Command::Heal).unwrap();
+            let duel = game.get_duel(duel_id).unwrap();
+            assert_eq!(duel.turn_deadline, Some(game.env().block_number() + 2));
+
+            // the hero whose turn it is can't claim, and the other hero has to wait for the deadline
+            test::set_caller::<EfinityEnvironment>(second);
+            assert_eq!(game.claim_duel_forfeit(), Err(Error::NotYourTurn));
+            test::set_caller::<EfinityEnvironment>(first);
+            test::advance_block::<EfinityEnvironment>();
+            test::advance_block::<EfinityEnvironment>();
+            assert_eq!(game.claim_duel_forfeit(), Err(Error::DuelTurnNotExpired));
+
+            // after the deadline the waiting hero wins both wagers, even while battles are paused
+            test::advance_block::<EfinityEnvironment>();
+            test::set_caller::<EfinityEnvironment>(alice());
+            game.pause(Feature::Battles).unwrap();
+            test::set_caller::<EfinityEnvironment>(first);
+            game.claim_duel_forfeit().unwrap();
+            assert!(game.get_duel(duel_id).is_none());
+            assert_eq!(game.get_gold_balance(first), 20);
+            assert_eq!(game.get_gold_balance(second), 0);
+            assert!(game.heroes.get(first).unwrap().rating > game.config.pvp_initial_rating);
+            assert!(game.heroes.get(second).unwrap().duel_id.is_none());
+        }
+
+        /// Test `commit_battle`, `start_battle` and `advance_battle` with commit-reveal
+        #[ink::test]
+        fn test_commit_reveal() {
+            let mut game = init_game(Config {
+                commit_reveal_enabled: true,
+                hero_max_health: 100,
+                enemy_health_range: (100, 100).into(),
+                ..Default::default()
+            });
+            game.create_hero().unwrap();
+
+            // a commitment is required
+            assert_eq!(game.start_battle(None), Err(Error::CommitmentRequired));
+
+            // commit to a hash chain of three secrets
+            let hash = |input: &[u8; 32]| game.env().hash_bytes::<Blake2x256>(input);
+            let third_secret = [2_u8; 32];
+            let second_secret = hash(&third_secret);
+            let first_secret = hash(&second_secret);
+            let commitment = hash(&first_secret);
+            game.commit_battle(commitment).unwrap();
+            assert_eq!(
+                game.commit_battle(commitment),
+                Err(Error::CommitmentAlreadyMade)
+            );
+
+            // only the hash and block are stored, so nothing the outcome depends on is known before the reveal,
+            // which can't happen in the same block
+            let block = game.env().block_number();
+            assert_eq!(
+                game.heroes.get(alice()).unwrap().commitment,
+                Some(Commitment {
+                    hash: commitment,
+                    block_number: block,
+                })
+            );
+            assert_eq!(
+                game.start_battle(Some(first_secret)),
+                Err(Error::RevealTooEarly)
+            );
+            test::advance_block::<EfinityEnvironment>();
+
+            // the first secret decides the enemy
+            assert_eq!(game.start_battle(None), Err(Error::InvalidReveal));
+            assert_eq!(
+                game.start_battle(Some(second_secret)),
+                Err(Error::InvalidReveal)
+            );
+            game.start_battle(Some(first_secret)).unwrap();
+            let hero = game.heroes.get(alice()).unwrap();
+            assert!(hero.commitment.is_none());
+            assert_eq!(
+                hero.battle.unwrap().commitment,
+                Some(Commitment {
+                    hash: first_secret,
+                    block_number: block + 1,
+                })
+            );
+            assert_eq!(
+                game.advance_battle(Command::Attack, Some(second_secret)),
+                Err(Error::RevealTooEarly)
             );
+            test::advance_block::<EfinityEnvironment>();
 
-            // lose the battle
-            game.advance_battle(Command::Attack).unwrap();
-            let hero = game.get_hero(alice()).unwrap();
-            assert!(hero.battle.is_none());
-
-            // health and victory count should be reset
-            assert_eq!(hero.health, game.config.hero_max_health);
-            assert_eq!(hero.consecutive_victory_count, 0);
-
-            // the hat token was burned
+            // the rest must be revealed in order, one block after another
             assert_eq!(
-                game.env().extension().balance_of(
-                    game.collection_id,
-                    hat_id,
-                    game.env().account_id()
-                ),
-                0
+                game.advance_battle(Command::Attack, None),
+                Err(Error::InvalidReveal)
             );
+            assert_eq!(
+                game.advance_battle(Command::Attack, Some(third_secret)),
+                Err(Error::InvalidReveal)
+            );
+            game.advance_battle(Command::Attack, Some(second_secret))
+                .unwrap();
+            let battle = game.heroes.get(alice()).unwrap().battle.unwrap();
+            assert_eq!(battle.round_number, 1);
+            assert_eq!(battle.commitment.unwrap().hash, second_secret);
+
+            // the next round uses the next secret in the chain
+            test::advance_block::<EfinityEnvironment>();
+            game.advance_battle(Command::Attack, Some(third_secret))
+                .unwrap();
+            let battle = game.heroes.get(alice()).unwrap().battle.unwrap();
+            assert_eq!(battle.round_number, 2);
         }
 
         /// Test `equip` function
@@ -1086,7 +4551,9 @@ mod game {
             assert_eq!(game.equip(10).unwrap_err(), Error::InvalidEquipment);
 
             // Mint a new token. It will still fail because there is no weapon attribute
-            let new_weapon_id = game.mint_nft(alice(), TokenType::Weapon, false).unwrap();
+            let new_weapon_id = game
+                .mint_nft(alice(), TokenType::Weapon, false, None)
+                .unwrap();
             assert_eq!(
                 game.equip(new_weapon_id).unwrap_err(),
                 Error::InvalidEquipment
@@ -1118,6 +4585,39 @@ mod game {
             assert_eq!(hero.weapon_id, new_weapon_id);
         }
 
+        /// Test `equip` with tokens the caller can't equip
+        #[ink::test]
+        fn test_equip_rejections() {
+            let mut game = init_game(Config {
+                enemy_wearing_hat_chance: 100,
+                ..Default::default()
+            });
+            let hero = game.create_hero().unwrap();
+
+            // cannot equip a weapon that is already equipped
+            assert_eq!(game.equip(hero.weapon_id), Err(Error::TokenIsFrozen));
+
+            // cannot equip a frozen weapon
+            let frozen_weapon_id = game
+                .mint_nft(alice(), TokenType::Weapon, true, None)
+                .unwrap();
+            game.add_equipment_attribute(frozen_weapon_id, (1, 1).into())
+                .unwrap();
+            assert_eq!(game.equip(frozen_weapon_id), Err(Error::TokenIsFrozen));
+
+            // cannot equip bob's weapon
+            test::set_caller::<EfinityEnvironment>(bob());
+            let bob_hero = game.create_hero().unwrap();
+            test::set_caller::<EfinityEnvironment>(alice());
+            assert_eq!(game.equip(bob_hero.weapon_id), Err(Error::TokenNotOwned));
+
+            // cannot equip a hat the contract holds for a battle
+            game.start_battle(None).unwrap();
+            let hero = game.heroes.get(alice()).unwrap();
+            let hat_id = hero.battle.unwrap().enemy.hat_id.unwrap();
+            assert_eq!(game.equip(hat_id), Err(Error::TokenNotOwned));
+        }
+
         /// Test `unequip_hat` function
         #[ink::test]
         fn test_unequip_hat() {
@@ -1125,7 +4625,7 @@ mod game {
             game.create_hero().unwrap();
 
             // equip a hat
-            let hat_id = game.mint_nft(alice(), TokenType::Hat, false).unwrap();
+            let hat_id = game.mint_nft(alice(), TokenType::Hat, false, None).unwrap();
             game.equip(hat_id).unwrap();
 
             // hero should be wearing the hat
@@ -1176,7 +4676,7 @@ mod game {
             game.rest().unwrap();
             assert_eq!(game.get_gold_balance(alice()), 10);
             assert_eq!(
-                game.get_hero(alice()).unwrap().health,
+                game.get_hero(alice()).unwrap().hero.health,
                 config.hero_max_health
             );
         }
@@ -1204,7 +4704,7 @@ mod game {
             assert_eq!(game.get_gold_balance(alice()), 20);
             game.buy_potion(2).unwrap();
             assert_eq!(game.get_gold_balance(alice()), 0);
-            assert_eq!(game.get_hero(alice()).unwrap().potion_count, 2);
+            assert_eq!(game.get_hero(alice()).unwrap().hero.potion_count, 2);
         }
 
         /// Test `buy_weapon` function
@@ -1235,6 +4735,460 @@ mod game {
                 .contains(metadata.strength));
         }
 
+        /// Test `sell_item` function
+        #[ink::test]
+        fn test_sell_item() {
+            let mut game = init_game(Config {
+                weapon_cost: 0,
+                purchased_weapon_strength_range: (8, 8).into(),
+                item_sell_base_price: 10,
+                item_sell_price_per_point: 5,
+                rest_cost: 50,
+                gold_burn_percentage: 0,
+                ..Default::default()
+            });
+            let collection_id = game.collection_id;
+            let contract_id = game.env().account_id();
+            let balance_of = |game: &Game, token_id, account_id| {
+                game.env()
+                    .extension()
+                    .balance_of(collection_id, token_id, account_id)
+            };
+            let hero = game.create_hero().unwrap();
+            let weapon_id = game.buy_weapon().unwrap();
+
+            // only unequipped weapons and hats held by the caller can be sold
+            assert_eq!(game.sell_item(hero.weapon_id), Err(Error::TokenIsFrozen));
+            assert_eq!(
+                game.sell_item(game.gold_token_id),
+                Err(Error::ItemNotSellable)
+            );
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.sell_item(weapon_id), Err(Error::TokenNotOwned));
+
+            // the seller is paid from the treasury, so the item is kept while it can't cover the price
+            test::set_caller::<EfinityEnvironment>(alice());
+            assert_eq!(game.sell_item(weapon_id), Err(Error::NotEnoughTreasuryGold));
+            assert_eq!(balance_of(&game, weapon_id, alice()), 1);
+
+            // the weapon is burned and the seller is paid for its strength
+            game.mint_gold(50).unwrap();
+            game.rest().unwrap();
+            assert_eq!(game.get_treasury_balance(), 50);
+            assert_eq!(game.sell_item(weapon_id), Ok(50));
+            assert_eq!(game.get_gold_balance(alice()), 50);
+            assert_eq!(game.get_treasury_balance(), 0);
+            for account_id in [alice(), contract_id] {
+                assert_eq!(balance_of(&game, weapon_id, account_id), 0);
+            }
+
+            // the part the treasury can't cover is minted if configured
+            let weapon_id = game.buy_weapon().unwrap();
+            game.mutate_config(ConfigMutation {
+                item_sales_mint_shortfall: Some(true),
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(game.sell_item(weapon_id), Ok(50));
+            assert_eq!(game.get_gold_balance(alice()), 100);
+        }
+
+        /// Test `forge` function
+        #[ink::test]
+        fn test_forge() {
+            let mut game = init_game(Config {
+                weapon_cost: 0,
+                purchased_weapon_strength_range: (10, 10).into(),
+                forge_cost: 10,
+                forge_strength_percentage: 60,
+                forge_bonus_strength_range: (2, 2).into(),
+                ..Default::default()
+            });
+            let collection_id = game.collection_id;
+            let hero = game.create_hero().unwrap();
+            let weapon_a = game.buy_weapon().unwrap();
+            let weapon_b = game.buy_weapon().unwrap();
+
+            // both weapons must be different, unequipped, and affordable
+            assert_eq!(
+                game.forge(weapon_a, weapon_a),
+                Err(Error::CannotForgeSameWeapon)
+            );
+            assert_eq!(
+                game.forge(weapon_a, hero.weapon_id),
+                Err(Error::TokenIsFrozen)
+            );
+            assert_eq!(
+                game.forge(weapon_a, game.gold_token_id),
+                Err(Error::InvalidEquipment)
+            );
+            assert_eq!(game.forge(weapon_a, weapon_b), Err(Error::NotEnoughGold));
+
+            // both weapons are burned and the new one has 60% of their strength plus the bonus
+            game.mint_gold(10).unwrap();
+            let token_id = game.forge(weapon_a, weapon_b).unwrap();
+            assert_eq!(game.get_gold_balance(alice()), 0);
+            assert_eq!(game.get_metadata(token_id).unwrap().unwrap().strength, 14);
+            for (token_id, balance) in [(weapon_a, 0), (weapon_b, 0), (token_id, 1)] {
+                assert_eq!(
+                    game.env()
+                        .extension()
+                        .balance_of(collection_id, token_id, alice()),
+                    balance
+                );
+            }
+
+            // the new weapon can be equipped
+            game.equip(token_id).unwrap();
+        }
+
+        /// Test `upgrade_weapon` function
+        #[ink::test]
+        fn test_upgrade_weapon() {
+            let mut game = init_game(Config {
+                starting_weapon_strength_range: (5, 5).into(),
+                weapon_upgrade_table: vec![
+                    WeaponUpgrade {
+                        cost: 10,
+                        strength_increase: 2,
+                        failure_chance: 0,
+                    },
+                    WeaponUpgrade {
+                        cost: 20,
+                        strength_increase: 3,
+                        failure_chance: 50,
+                    },
+                ],
+                ..Default::default()
+            });
+            randomness::set_source(ScriptedRandomness::new([
+                5,  // weapon strength
+                0,  // first upgrade succeeds
+                10, // second upgrade fails
+                99, // second upgrade succeeds
+            ]));
+            let weapon_id = game.create_hero().unwrap().weapon_id;
+            let strength_and_level = |game: &Game| {
+                let metadata = game.get_metadata(weapon_id).unwrap().unwrap();
+                (metadata.strength, metadata.upgrade_level)
+            };
+
+            // bob can't upgrade alice's weapon
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.upgrade_weapon(weapon_id), Err(Error::TokenNotOwned));
+
+            // the equipped weapon is upgraded and stays frozen
+            test::set_caller::<EfinityEnvironment>(alice());
+            assert_eq!(game.upgrade_weapon(weapon_id), Err(Error::NotEnoughGold));
+            game.mint_gold(50).unwrap();
+            assert_eq!(game.upgrade_weapon(weapon_id), Ok(true));
+            assert_eq!(strength_and_level(&game), (7, 1));
+            assert!(game.is_frozen(weapon_id));
+            assert_eq!(game.get_gold_balance(alice()), 40);
+
+            // a failed upgrade still costs gold
+            assert_eq!(game.upgrade_weapon(weapon_id), Ok(false));
+            assert_eq!(strength_and_level(&game), (7, 1));
+            assert_eq!(game.get_gold_balance(alice()), 20);
+            assert_eq!(game.upgrade_weapon(weapon_id), Ok(true));
+            assert_eq!(strength_and_level(&game), (10, 2));
+            assert_eq!(game.get_gold_balance(alice()), 0);
+
+            // there are no more levels in the table
+            assert_eq!(game.upgrade_weapon(weapon_id), Err(Error::WeaponAtMaxLevel));
+        }
+
+        /// Test weapon durability and `repair_weapon` function
+        #[ink::test]
+        fn test_weapon_durability() {
+            // weapons have no durability by default. Bob's hero is used because heroes stay in storage when the
+            // game is initialized again below.
+            let mut game = init_game(Default::default());
+            test::set_caller::<EfinityEnvironment>(bob());
+            let weapon_id = game.create_hero().unwrap().weapon_id;
+            assert_eq!(
+                game.repair_weapon(weapon_id),
+                Err(Error::WeaponHasNoDurability)
+            );
+            test::set_caller::<EfinityEnvironment>(alice());
+
+            let mut game = init_game(Config {
+                starting_weapon_strength_range: (10, 10).into(),
+                weapon_durability_range: Some((2, 2).into()),
+                broken_weapon_damage_percentage: 50,
+                repair_cost_per_point: 3,
+                attack_variance: 0,
+                enemy_health_range: (100, 100).into(),
+                enemy_strength_range: (0, 0).into(),
+                enemy_wearing_hat_chance: 0,
+                flee_chance: 100,
+                flee_gold_penalty: 0,
+                ..Default::default()
+            });
+            let weapon_id = game.create_hero().unwrap().weapon_id;
+            let durability = |game: &Game| {
+                game.get_metadata(weapon_id)
+                    .unwrap()
+                    .unwrap()
+                    .durability
+                    .unwrap()
+            };
+            assert_eq!(durability(&game), Durability::new(2));
+
+            // each attack wears the weapon down until it breaks
+            game.start_battle(None).unwrap();
+            let enemy_health = |game: &Game| {
+                game.get_hero(alice())
+                    .unwrap()
+                    .hero
+                    .battle
+                    .unwrap()
+                    .enemy
+                    .health
+            };
+            game.advance_battle(Command::Attack, None).unwrap();
+            assert_eq!(enemy_health(&game), 90);
+            game.advance_battle(Command::Attack, None).unwrap();
+            assert_eq!(enemy_health(&game), 80);
+            assert!(durability(&game).is_broken());
+
+            // a broken weapon deals half damage
+            game.advance_battle(Command::Attack, None).unwrap();
+            assert_eq!(enemy_health(&game), 75);
+            assert_eq!(durability(&game).current, 0);
+
+            // the equipped weapon is repaired outside of battle
+            assert_eq!(game.repair_weapon(weapon_id), Err(Error::HeroIsInBattle));
+            game.advance_battle(Command::Flee, None).unwrap();
+            assert_eq!(game.repair_weapon(weapon_id), Err(Error::NotEnoughGold));
+            game.mint_gold(6).unwrap();
+            assert_eq!(game.repair_weapon(weapon_id), Ok(6));
+            assert_eq!(durability(&game), Durability::new(2));
+            assert_eq!(game.get_gold_balance(alice()), 0);
+
+            // a weapon with all of its durability can't be repaired
+            assert_eq!(game.repair_weapon(weapon_id), Err(Error::WeaponNotDamaged));
+        }
+
+        /// Test rarity of weapons and hats
+        #[ink::test]
+        fn test_rarity() {
+            let mut game = init_game(Config {
+                weapon_cost: 0,
+                starting_weapon_strength_range: (5, 5).into(),
+                purchased_weapon_strength_range: (5, 5).into(),
+                rarity_tiers: [
+                    (70, (1, 2), (0, 1)),
+                    (20, (3, 5), (1, 2)),
+                    (8, (6, 8), (2, 3)),
+                    (2, (9, 12), (3, 5)),
+                ]
+                .into_iter()
+                .map(|(weight, strength_range, defense_range)| RarityTier {
+                    weight,
+                    strength_range: strength_range.into(),
+                    defense_range: defense_range.into(),
+                })
+                .collect(),
+                ..Default::default()
+            });
+            randomness::set_source(ScriptedRandomness::new([
+                0, 2, // common starting weapon with 2 strength
+                98, 11, // legendary weapon with 11 strength
+                70, 2, // rare hat with 2 defense
+                5, // weapon with no rarity
+            ]));
+            let hero = game.create_hero().unwrap();
+            let legendary_weapon_id = game.buy_weapon().unwrap();
+            let hat_id = game.mint_nft(alice(), TokenType::Hat, false, None).unwrap();
+            game.add_hat_attribute(hat_id, (0, 0).into(), None).unwrap();
+
+            // the rarity is encoded in the token id and stored in the metadata
+            for (token_id, rarity) in [
+                (hero.weapon_id, Rarity::Common),
+                (legendary_weapon_id, Rarity::Legendary),
+                (hat_id, Rarity::Rare),
+            ] {
+                let info = game.get_token_info(token_id);
+                assert_eq!(info.rarity, Some(rarity));
+                assert_eq!(
+                    game.get_metadata(token_id).unwrap().unwrap().rarity,
+                    Some(rarity)
+                );
+            }
+
+            // items roll their stats from the range of their rarity
+            let metadata = |token_id| game.get_metadata(token_id).unwrap().unwrap();
+            assert_eq!(metadata(hero.weapon_id).strength, 2);
+            assert_eq!(metadata(legendary_weapon_id).strength, 11);
+            assert_eq!(metadata(hat_id).defense, 2);
+
+            // items have no rarity without tiers, and trophies never have one
+            let mutation = ConfigMutation {
+                rarity_tiers: Some(Vec::new()),
+                ..Default::default()
+            };
+            game.mutate_config(mutation).unwrap();
+            let weapon_id = game.buy_weapon().unwrap();
+            assert!(game.get_token_info(weapon_id).rarity.is_none());
+            assert_eq!(game.get_metadata(weapon_id).unwrap().unwrap().strength, 5);
+            let trophy_id = game
+                .mint_nft(alice(), TokenType::Trophy, false, None)
+                .unwrap();
+            assert!(game.get_token_info(trophy_id).rarity.is_none());
+        }
+
+        /// Test marketplace functions
+        #[ink::test]
+        fn test_marketplace() {
+            let mut game = init_game(Config {
+                weapon_cost: 0,
+                marketplace_fee_percentage: 10,
+                ..Default::default()
+            });
+            let collection_id = game.collection_id;
+            let contract_id = game.env().account_id();
+            let balance_of = |game: &Game, token_id, account_id| {
+                game.env()
+                    .extension()
+                    .balance_of(collection_id, token_id, account_id)
+            };
+            let hero = game.create_hero().unwrap();
+            let weapon_id = game.buy_weapon().unwrap();
+
+            // only unequipped weapons and hats held by the caller can be listed
+            assert_eq!(
+                game.list_item(hero.weapon_id, 100),
+                Err(Error::TokenIsFrozen)
+            );
+            assert_eq!(
+                game.list_item(game.gold_token_id, 100),
+                Err(Error::ItemNotSellable)
+            );
+
+            // the listed weapon is held by the contract
+            game.list_item(weapon_id, 100).unwrap();
+            assert_eq!(balance_of(&game, weapon_id, contract_id), 1);
+            assert_eq!(
+                game.get_listings(0, 10),
+                vec![Listing {
+                    seller: alice(),
+                    token_id: weapon_id,
+                    price: 100,
+                }]
+            );
+            assert_eq!(game.equip(weapon_id), Err(Error::TokenNotOwned));
+            assert_eq!(game.buy_listing(weapon_id), Err(Error::CannotBuyOwnListing));
+
+            // bob can't cancel alice's listing or buy it without enough gold
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.cancel_listing(weapon_id), Err(Error::NoPermission));
+            game.mint_gold(99).unwrap();
+            assert_eq!(game.buy_listing(weapon_id), Err(Error::NotEnoughGold));
+
+            // the seller is paid and the fee goes to the treasury
+            game.mint_gold(1).unwrap();
+            game.buy_listing(weapon_id).unwrap();
+            assert_eq!(balance_of(&game, weapon_id, bob()), 1);
+            assert_eq!(game.get_gold_balance(bob()), 0);
+            assert_eq!(game.get_gold_balance(alice()), 90);
+            assert_eq!(game.get_treasury_balance(), 10);
+            assert!(game.get_listings(0, 10).is_empty());
+            assert_eq!(game.buy_listing(weapon_id), Err(Error::ListingNotFound));
+
+            // cancelling returns the item to the seller
+            game.list_item(weapon_id, 5).unwrap();
+            game.cancel_listing(weapon_id).unwrap();
+            assert_eq!(balance_of(&game, weapon_id, bob()), 1);
+            assert!(game.get_listings(0, 10).is_empty());
+
+            // removing a listing moves the last one into its position
+            test::set_caller::<EfinityEnvironment>(alice());
+            let first_id = game.buy_weapon().unwrap();
+            let second_id = game.buy_weapon().unwrap();
+            let third_id = game.buy_weapon().unwrap();
+            game.list_item(first_id, 1).unwrap();
+            game.list_item(second_id, 2).unwrap();
+            game.list_item(third_id, 3).unwrap();
+            game.cancel_listing(first_id).unwrap();
+            let listed_ids = |game: &Game, offset, limit| {
+                game.get_listings(offset, limit)
+                    .iter()
+                    .map(|listing| listing.token_id)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(listed_ids(&game, 0, 10), vec![third_id, second_id]);
+            assert_eq!(listed_ids(&game, 1, 10), vec![second_id]);
+            assert!(listed_ids(&game, 2, 10).is_empty());
+
+            // listings can be cancelled while the marketplace is paused
+            game.pause(Feature::Marketplace).unwrap();
+            assert_eq!(game.list_item(first_id, 1), Err(Error::GamePaused));
+            game.cancel_listing(second_id).unwrap();
+            assert_eq!(balance_of(&game, second_id, alice()), 1);
+            assert_eq!(listed_ids(&game, 0, 10), vec![third_id]);
+        }
+
+        /// Test treasury functions
+        #[ink::test]
+        fn test_treasury() {
+            let mut game = init_game(Config {
+                rest_cost: 50,
+                gold_burn_percentage: 40,
+                enemy_drops_from_treasury: true,
+                enemy_health_range: (1, 1).into(),
+                enemy_strength_range: (0, 0).into(),
+                enemy_gold_drop_range: (20, 20).into(),
+                enemy_wearing_hat_chance: 0,
+                boss_victory_threshold: 0,
+                ..Default::default()
+            });
+            let accounts = accounts();
+            let contract_id = game.env().account_id();
+            game.create_hero().unwrap();
+            game.mint_gold(100).unwrap();
+
+            // 40% of spent gold is burned and the rest is kept
+            game.rest().unwrap();
+            assert_eq!(game.get_gold_balance(alice()), 50);
+            assert_eq!(game.get_treasury_balance(), 30);
+            assert_eq!(game.get_gold_balance(contract_id), 30);
+
+            // enemy drops are paid from the treasury
+            game.start_battle(None).unwrap();
+            game.advance_battle(Command::Attack, None).unwrap();
+            assert_eq!(game.get_gold_balance(alice()), 70);
+            assert_eq!(game.get_treasury_balance(), 10);
+
+            // only a treasurer can pay out of the treasury
+            test::set_caller::<EfinityEnvironment>(bob());
+            assert_eq!(game.withdraw_treasury(bob(), 5), Err(Error::NoPermission));
+            test::set_caller::<EfinityEnvironment>(alice());
+            assert_eq!(
+                game.withdraw_treasury(bob(), 11),
+                Err(Error::NotEnoughTreasuryGold)
+            );
+            game.withdraw_treasury(bob(), 4).unwrap();
+            assert_eq!(game.get_gold_balance(bob()), 4);
+            assert_eq!(game.get_treasury_balance(), 6);
+
+            // nothing is paid if the treasury can't cover every payment
+            assert_eq!(
+                game.distribute_treasury(vec![(bob(), 3), (accounts.charlie, 4)]),
+                Err(Error::NotEnoughTreasuryGold)
+            );
+            game.distribute_treasury(vec![(bob(), 3), (accounts.charlie, 3)])
+                .unwrap();
+            assert_eq!(game.get_gold_balance(bob()), 7);
+            assert_eq!(game.get_gold_balance(accounts.charlie), 3);
+            assert_eq!(game.get_treasury_balance(), 0);
+
+            // drops are minted once the treasury is empty
+            game.start_battle(None).unwrap();
+            game.advance_battle(Command::Attack, None).unwrap();
+            assert_eq!(game.get_gold_balance(alice()), 90);
+        }
+
         /// Test `calculate_attack_power` function
         #[ink::test]
         fn test_calculate_attack_power() {
@@ -1248,29 +5202,252 @@ mod game {
             // verify several cases of variance 2
             let mut game = new_game_with_attack_variance(2);
             for _ in 0..10 {
-                assert!(Range::new(8, 12).contains(game.calculate_attack_power(10)));
+                assert!(Range::new(8, 12).contains(game.calculate_attack_power(10, None)));
             }
 
             // verify several cases of variance 5
             let mut game = new_game_with_attack_variance(5);
             for _ in 0..10 {
-                assert!(Range::new(5, 15).contains(game.calculate_attack_power(10)));
+                assert!(Range::new(5, 15).contains(game.calculate_attack_power(10, None)));
             }
 
             // verify several cases of variance 0
             let mut game = new_game_with_attack_variance(0);
             for _ in 0..10 {
-                assert_eq!(game.calculate_attack_power(10), 10);
+                assert_eq!(game.calculate_attack_power(10, None), 10);
+            }
+
+            // attack power does not wrap around when the variance is larger than the strength
+            let mut game = new_game_with_attack_variance(5);
+            for _ in 0..10 {
+                assert!(Range::new(0, 6).contains(game.calculate_attack_power(1, None)));
+            }
+        }
+
+        /// Test `get_leaderboard` function
+        #[ink::test]
+        fn test_leaderboard() {
+            let mut game = init_game(Config {
+                leaderboard_size: 3,
+                ..Default::default()
+            });
+            let accounts = accounts();
+            game.update_leaderboard(accounts.alice, 1000);
+            game.update_leaderboard(accounts.bob, 1200);
+            game.update_leaderboard(accounts.charlie, 1100);
+            game.update_leaderboard(accounts.django, 900);
+
+            // the lowest rating does not fit
+            let hero_ids = |entries: Vec<LeaderboardEntry>| -> Vec<AccountId> {
+                entries.into_iter().map(|entry| entry.hero_id).collect()
+            };
+            assert_eq!(
+                hero_ids(game.get_leaderboard(0, 10)),
+                vec![accounts.bob, accounts.charlie, accounts.alice]
+            );
+
+            // a changed rating moves the hero
+            game.update_leaderboard(accounts.alice, 1300);
+            assert_eq!(
+                hero_ids(game.get_leaderboard(0, 10)),
+                vec![accounts.alice, accounts.bob, accounts.charlie]
+            );
+
+            // paging
+            assert_eq!(hero_ids(game.get_leaderboard(1, 1)), vec![accounts.bob]);
+            assert!(game.get_leaderboard(3, 1).is_empty());
+        }
+
+        /// Test that a hero's rating can't be reset by creating the hero again
+        #[ink::test]
+        fn test_rating_not_reset() {
+            let mut game = init_game(Default::default());
+            let hero = game.create_hero().unwrap();
+            assert_eq!(hero.rating, game.config.pvp_initial_rating);
+
+            // the hero lost rating in duels
+            let mut hero = game.heroes.get(alice()).unwrap();
+            hero.rating = 900;
+            game.heroes.insert(alice(), &hero);
+
+            // creating the hero again fails and keeps the rating
+            assert_eq!(game.create_hero(), Err(Error::HeroAlreadyExists));
+            assert_eq!(game.get_hero(alice()).unwrap().hero, hero);
+        }
+
+        /// Test `end_season` function and season stats
+        #[ink::test]
+        fn test_seasons() {
+            let mut game = init_game(Config {
+                enemy_health_range: (1, 1).into(),
+                enemy_strength_range: (0, 0).into(),
+                enemy_wearing_hat_chance: 0,
+                boss_victory_threshold: 0,
+                season_trophy_count: 2,
+                ..Default::default()
+            });
+            let accounts = accounts();
+
+            // every battle is won in one round
+            let win_battle = |game: &mut Game, hero_id: AccountId| {
+                test::set_caller::<EfinityEnvironment>(hero_id);
+                game.start_battle(None).unwrap();
+                game.advance_battle(Command::Attack, None).unwrap();
+            };
+            for hero_id in [accounts.alice, accounts.bob, accounts.charlie] {
+                test::set_caller::<EfinityEnvironment>(hero_id);
+                game.create_hero().unwrap();
             }
+            win_battle(&mut game, accounts.bob);
+            win_battle(&mut game, accounts.alice);
+            win_battle(&mut game, accounts.bob);
+            win_battle(&mut game, accounts.charlie);
+            win_battle(&mut game, accounts.charlie);
+
+            // heroes with the same count keep the order they reached it in
+            let season_leaderboard = game.get_season_leaderboard(0, 10);
+            let hero_ids: Vec<AccountId> = season_leaderboard
+                .iter()
+                .map(|entry| entry.hero_id)
+                .collect();
+            assert_eq!(
+                hero_ids,
+                vec![accounts.bob, accounts.charlie, accounts.alice]
+            );
+            assert_eq!(
+                game.get_hero(accounts.bob).unwrap().season_stats,
+                SeasonStats {
+                    season: 1,
+                    victory_count: 2,
+                    consecutive_victory_count: 2,
+                    highest_consecutive_victory_count: 2,
+                }
+            );
+
+            // only an admin can end the season
+            assert_eq!(game.end_season(), Err(Error::NoPermission));
+            test::set_caller::<EfinityEnvironment>(accounts.alice);
+            let first_trophy_id = game.next_token_id;
+            game.end_season().unwrap();
+            assert_eq!(game.get_season(), 2);
+            assert_eq!(game.get_season_results(1), Some(season_leaderboard));
+            assert!(game.get_season_leaderboard(0, 10).is_empty());
+
+            // the top two heroes received trophies, which can't be equipped
+            let bob_trophy_id =
+                WrappedTokenId::new(first_trophy_id, Some(TokenType::SeasonTrophy)).0;
+            let charlie_trophy_id =
+                WrappedTokenId::new(first_trophy_id + 1, Some(TokenType::SeasonTrophy)).0;
+            let collection_id = game.collection_id;
+            let balance_of = |game: &Game, token_id, account_id| {
+                game.env()
+                    .extension()
+                    .balance_of(collection_id, token_id, account_id)
+            };
+            assert_eq!(balance_of(&game, bob_trophy_id, accounts.bob), 1);
+            assert_eq!(balance_of(&game, charlie_trophy_id, accounts.charlie), 1);
+            test::set_caller::<EfinityEnvironment>(accounts.bob);
+            assert_eq!(game.equip(bob_trophy_id), Err(Error::InvalidEquipment));
+
+            // season stats start over but lifetime stats and equipment are kept
+            let hero_info = game.get_hero(accounts.bob).unwrap();
+            assert_eq!(hero_info.season_stats.season, 2);
+            assert_eq!(hero_info.season_stats.victory_count, 0);
+            assert_eq!(hero_info.hero.victory_count, 2);
+            assert_eq!(hero_info.hero.highest_consecutive_victory_count, 2);
+            win_battle(&mut game, accounts.bob);
+            let hero_info = game.get_hero(accounts.bob).unwrap();
+            assert_eq!(hero_info.season_stats.victory_count, 1);
+            assert_eq!(hero_info.hero.victory_count, 3);
+
+            // the streak from the last season doesn't carry over
+            assert_eq!(hero_info.season_stats.highest_consecutive_victory_count, 1);
+            assert_eq!(hero_info.hero.highest_consecutive_victory_count, 3);
+            assert_eq!(game.get_season_leaderboard(0, 10).len(), 1);
+        }
+
+        /// Test `elo_rating_delta` function
+        #[test]
+        fn test_elo_rating_delta() {
+            // equal ratings
+            assert_eq!(elo_rating_delta(1200, 1200, 32), 16);
+
+            // the favorite gains less than the underdog
+            assert_eq!(elo_rating_delta(1600, 1200, 32), 3);
+            assert_eq!(elo_rating_delta(1200, 1600, 32), 29);
+
+            // differences are capped
+            assert_eq!(elo_rating_delta(3000, 1000, 32), 0);
+            assert_eq!(elo_rating_delta(1000, 3000, 32), 32);
         }
 
-        /// Test `lerp` function
+        /// Test `sample_range` function
         #[test]
-        fn test_lerp() {
-            assert_eq!(lerp(0, 100, u32::MAX), 100);
-            assert_eq!(lerp(0, 100, (u32::MAX / 2) + 1), 50);
-            assert_eq!(lerp(0, 100, (u32::MAX / 10) + 1), 10);
-            assert_eq!(lerp(5, 100, 0), 5);
+        fn test_sample_range() {
+            let mut source = SeededRandomness::new(42);
+
+            // every value in a small range is equally likely
+            const SAMPLE_COUNT: u32 = 100_000;
+            let mut counts = [0_u32; 10];
+            for _ in 0..SAMPLE_COUNT {
+                let value = sample_range((0, 9).into(), || source.next_u32());
+                counts[value as usize] += 1;
+            }
+            for count in counts {
+                assert!(Range::new(9_500, 10_500).contains(count), "{}", count);
+            }
+
+            // both bounds of a wide range are reached
+            let mut reached_start = false;
+            let mut reached_end = false;
+            for _ in 0..SAMPLE_COUNT {
+                let value = sample_range((5, 1005).into(), || source.next_u32());
+                assert!(Range::new(5, 1005).contains(value));
+                reached_start |= value == 5;
+                reached_end |= value == 1005;
+            }
+            assert!(reached_start && reached_end);
+
+            // values that would bias the result are rejected. 2^32 is not divisible by 3, so u32::MAX is rejected.
+            let mut values = [u32::MAX, 4].into_iter();
+            assert_eq!(sample_range((0, 2).into(), || values.next().unwrap()), 1);
+
+            // a single value range
+            assert_eq!(sample_range((5, 5).into(), || source.next_u32()), 5);
+
+            // inverted ranges are swapped
+            for _ in 0..100 {
+                assert!(
+                    Range::new(5, 10).contains(sample_range((10, 5).into(), || source.next_u32()))
+                );
+            }
+
+            // the full range uses the number as is
+            assert_eq!(sample_range((0, u32::MAX).into(), || 1234), 1234);
+        }
+
+        /// Test `random_chance` function
+        #[ink::test]
+        fn test_random_chance() {
+            let mut game = init_game(Default::default());
+            randomness::set_source(SeededRandomness::new(7));
+
+            // a 50% chance is true about half of the time
+            const SAMPLE_COUNT: u32 = 10_000;
+            let successes = (0..SAMPLE_COUNT)
+                .filter(|_| game.random_chance(50, None))
+                .count() as u32;
+            assert!(
+                Range::new(4_800, 5_200).contains(successes),
+                "{}",
+                successes
+            );
+
+            // 0% and 100% never and always succeed
+            for _ in 0..1_000 {
+                assert!(!game.random_chance(0, None));
+                assert!(game.random_chance(100, None));
+            }
         }
 
         /// Test `Range` type
@@ -1309,6 +5486,18 @@ mod game {
             wrapped.set_token_type(None);
             assert!(wrapped.token_type().is_none());
             assert_eq!(wrapped.id(), token_id);
+
+            // the rarity is stored separately from the token type
+            wrapped.set_token_type(Some(Weapon));
+            wrapped.set_rarity(Some(Rarity::Legendary));
+            assert_eq!(wrapped.rarity(), Some(Rarity::Legendary));
+            assert_eq!(wrapped.token_type(), Some(Weapon));
+            assert_eq!(wrapped.id(), token_id);
+
+            wrapped.set_rarity(None);
+            assert!(wrapped.rarity().is_none());
+            assert_eq!(wrapped.token_type(), Some(Weapon));
+            assert_eq!(wrapped.id(), token_id);
         }
     }
 }
@@ -0,0 +1,198 @@
+//! Sources of randomness for the game. A revealed secret is always used if there is one. Otherwise the chain is
+//! used, unless another source is set with `set_source`, which only exists in tests or with the `test-randomness`
+//! feature.
+
+use crate::types::Range;
+use efinity_contracts::prelude::EfinityEnvironment;
+use ink_env::hash::Blake2x256;
+
+/// Provides random numbers to the game
+pub trait RandomnessSource {
+    /// Returns a random number in `range`. `subject` is unique for each number the game requests.
+    fn random_in_range(&mut self, subject: &[u8], range: Range) -> u32;
+}
+
+/// Randomness provided by the chain
+pub struct ChainRandomness;
+
+impl RandomnessSource for ChainRandomness {
+    fn random_in_range(&mut self, subject: &[u8], range: Range) -> u32 {
+        // get random hash
+        let (hash, _) = ink_env::random::<EfinityEnvironment>(subject)
+            .expect("couldn't decode randomized hash");
+        let mut bytes = [0_u8; 32];
+        bytes.copy_from_slice(hash.as_ref());
+        sample_range_from_hash(bytes, range)
+    }
+}
+
+/// Randomness derived only from the seed a revealed secret decides, so nothing else can change the numbers
+pub struct SecretRandomness {
+    /// Hashed again for each number
+    secret: [u8; 32],
+}
+
+impl SecretRandomness {
+    /// Create a new instance that derives numbers from `secret`
+    pub fn new(secret: [u8; 32]) -> Self {
+        Self { secret }
+    }
+}
+
+impl RandomnessSource for SecretRandomness {
+    fn random_in_range(&mut self, _subject: &[u8], range: Range) -> u32 {
+        // the subject is ignored because it can change after the secret is decided
+        let mut output = [0_u8; 32];
+        ink_env::hash_bytes::<Blake2x256>(&self.secret, &mut output);
+        self.secret = output;
+        sample_range_from_hash(output, range)
+    }
+}
+
+/// Returns a fixed sequence of numbers, so tests can assert exact outcomes
+#[cfg(any(test, feature = "test-randomness"))]
+pub struct ScriptedRandomness {
+    /// The numbers that have not been returned yet
+    values: std::collections::VecDeque<u32>,
+}
+
+#[cfg(any(test, feature = "test-randomness"))]
+impl ScriptedRandomness {
+    /// Create a new instance that returns `values` in order
+    pub fn new(values: impl IntoIterator<Item = u32>) -> Self {
+        Self {
+            values: values.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-randomness"))]
+impl RandomnessSource for ScriptedRandomness {
+    fn random_in_range(&mut self, _subject: &[u8], range: Range) -> u32 {
+        let value = self
+            .values
+            .pop_front()
+            .expect("ran out of scripted random numbers");
+        assert!(
+            range.contains(value),
+            "scripted random number {} is not in {:?}",
+            value,
+            range
+        );
+        value
+    }
+}
+
+/// Returns a reproducible sequence of numbers generated from a seed, for simulations
+#[cfg(any(test, feature = "test-randomness"))]
+pub struct SeededRandomness {
+    /// The state of the xorshift generator. Must never be 0.
+    state: u64,
+}
+
+#[cfg(any(test, feature = "test-randomness"))]
+impl SeededRandomness {
+    /// Create a new instance from `seed`
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed.max(1) }
+    }
+
+    /// Returns the next number from the generator
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 32) as u32
+    }
+}
+
+#[cfg(any(test, feature = "test-randomness"))]
+impl RandomnessSource for SeededRandomness {
+    fn random_in_range(&mut self, _subject: &[u8], range: Range) -> u32 {
+        sample_range(range, || self.next_u32())
+    }
+}
+
+#[cfg(any(test, feature = "test-randomness"))]
+std::thread_local! {
+    /// The source used instead of the chain
+    static SOURCE: core::cell::RefCell<Option<Box<dyn RandomnessSource>>> = Default::default();
+}
+
+/// Use `source` instead of the chain for the current thread
+#[cfg(any(test, feature = "test-randomness"))]
+pub fn set_source(source: impl RandomnessSource + 'static) {
+    SOURCE.with(|x| *x.borrow_mut() = Some(Box::new(source)));
+}
+
+/// Go back to using the chain for the current thread
+#[cfg(any(test, feature = "test-randomness"))]
+pub fn reset_source() {
+    SOURCE.with(|x| *x.borrow_mut() = None);
+}
+
+/// Calls `f` with `secret` if there is one. Otherwise calls it with the source set for the current thread, or the
+/// chain if there isn't one.
+pub fn with_source<R>(
+    secret: Option<&mut SecretRandomness>,
+    f: impl FnOnce(&mut dyn RandomnessSource) -> R,
+) -> R {
+    if let Some(secret) = secret {
+        return f(secret);
+    }
+
+    #[cfg(any(test, feature = "test-randomness"))]
+    {
+        if let Some(mut source) = SOURCE.with(|x| x.borrow_mut().take()) {
+            let result = f(source.as_mut());
+            SOURCE.with(|x| *x.borrow_mut() = Some(source));
+            return result;
+        }
+    }
+
+    f(&mut ChainRandomness)
+}
+
+/// Returns a number in `range` using numbers from `hash`, hashing it again if every number is rejected
+fn sample_range_from_hash(mut hash: [u8; 32], range: Range) -> u32 {
+    let mut index = 0;
+    sample_range(range, || {
+        if index == hash.len() {
+            let mut output = [0_u8; 32];
+            ink_env::hash_bytes::<Blake2x256>(&hash, &mut output);
+            hash = output;
+            index = 0;
+        }
+        let mut number = [0_u8; 4];
+        number.copy_from_slice(&hash[index..index + 4]);
+        index += 4;
+        u32::from_le_bytes(number)
+    })
+}
+
+/// Returns a uniformly distributed number in `range`, using numbers from `next_u32`. Numbers that would make
+/// some values more likely than others are rejected, so `next_u32` may be called more than once. The bounds are
+/// swapped if `start` is greater than `end`.
+pub fn sample_range(range: Range, mut next_u32: impl FnMut() -> u32) -> u32 {
+    let (start, end) = if range.start <= range.end {
+        (range.start, range.end)
+    } else {
+        (range.end, range.start)
+    };
+
+    // every number is in the range
+    let length = end as u64 - start as u64 + 1;
+    if length > u32::MAX as u64 {
+        return next_u32();
+    }
+
+    // only accept numbers below the largest multiple of the length
+    const NUMBER_COUNT: u64 = 1 << 32;
+    let accepted_count = NUMBER_COUNT - NUMBER_COUNT % length;
+    loop {
+        let number = next_u32() as u64;
+        if number < accepted_count {
+            return start + (number % length) as u32;
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use efinity_contracts::{TokenBalance, TokenId};
+use efinity_contracts::{AccountId, TokenBalance, TokenId};
+use ink_prelude::vec::Vec;
 use ink_storage::traits::{PackedLayout, SpreadAllocate, SpreadLayout};
 use scale::{Decode, Encode};
 use scale_info::TypeInfo;
@@ -18,6 +19,8 @@ pub enum Error {
     AttributeDecodeFailed,
     /// A hero does not exist for the provided account id
     HeroNotFound,
+    /// The caller already has a hero
+    HeroAlreadyExists,
     /// This operation is not allowed while in battle
     HeroIsInBattle,
     /// This operation is only allowed while in battle
@@ -26,6 +29,75 @@ pub enum Error {
     HeroHasNoPotions,
     /// The provided account id does not have enough gold
     NotEnoughGold,
+    /// The treasury does not have enough gold
+    NotEnoughTreasuryGold,
+    /// An enemy template with this id already exists
+    EnemyTemplateAlreadyExists,
+    /// An enemy template with this id does not exist
+    EnemyTemplateNotFound,
+    /// The bestiary already has the maximum number of templates
+    TooManyEnemyTemplates,
+    /// A hero cannot challenge itself to a duel
+    CannotDuelSelf,
+    /// The opponent is already in a battle or duel
+    OpponentIsInBattle,
+    /// This operation is only allowed while in a duel
+    HeroNotInDuel,
+    /// A duel with this id does not exist
+    DuelNotFound,
+    /// The duel has already been accepted
+    DuelAlreadyAccepted,
+    /// The duel has not been accepted yet
+    DuelNotAccepted,
+    /// It is the other hero's turn in the duel
+    NotYourTurn,
+    /// The hero whose turn it is still has time to take it
+    DuelTurnNotExpired,
+    /// A commitment must be made before starting a battle while commit-reveal is enabled
+    CommitmentRequired,
+    /// The hero already has a commitment that has not been used to start a battle
+    CommitmentAlreadyMade,
+    /// The revealed secret is missing or does not match the battle's commitment
+    InvalidReveal,
+    /// A secret can't be revealed in the block its commitment was recorded in
+    RevealTooEarly,
+    /// The caller does not own the token
+    TokenNotOwned,
+    /// The token is frozen, which means it's already equipped
+    TokenIsFrozen,
+    /// Only weapons and hats can be sold
+    ItemNotSellable,
+    /// The token is not listed on the marketplace
+    ListingNotFound,
+    /// A seller cannot buy their own listing
+    CannotBuyOwnListing,
+    /// A weapon cannot be forged with itself
+    CannotForgeSameWeapon,
+    /// The weapon has reached the last level of the upgrade table
+    WeaponAtMaxLevel,
+    /// The weapon does not have durability, so it can't be repaired
+    WeaponHasNoDurability,
+    /// The weapon has all of its durability, so there is nothing to repair
+    WeaponNotDamaged,
+    /// There is no pending owner
+    NoPendingOwner,
+    /// The game or this feature of it is paused
+    GamePaused,
+    /// There is no config in the history with this version
+    ConfigVersionNotFound,
+    /// A scheduled config mutation with this id does not exist
+    ConfigScheduleNotFound,
+    /// There are already `MAX_CONFIG_SCHEDULES` scheduled config mutations
+    TooManyConfigSchedules,
+    /// The revert block of a scheduled config mutation must come after its activation block
+    InvalidSchedule,
+    /// A config value is not allowed
+    InvalidConfig {
+        /// The field with the invalid value
+        field: ConfigField,
+        /// Why the value is invalid
+        reason: InvalidConfigReason,
+    },
 }
 
 impl From<efinity_contracts::Error> for Error {
@@ -39,6 +111,41 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 // Game
 
+/// A role that grants access to privileged messages. The owner implicitly has every role.
+#[derive(Debug, Encode, Decode, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo))]
+pub enum Role {
+    /// Can grant and revoke roles
+    Admin,
+    /// Can change the config and the bestiary
+    ConfigManager,
+    /// Can pause the game
+    Pauser,
+    /// Can manage the treasury
+    Treasurer,
+}
+
+/// A group of gameplay messages that can be paused together
+#[derive(Debug, Encode, Decode, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo))]
+pub enum Feature {
+    /// Every gameplay message
+    All,
+    /// Creating heroes
+    Heroes,
+    /// Battles and duels
+    Battles,
+    /// Equipping and unequipping items
+    Equipment,
+    /// Buying potions and weapons, selling items, forging, upgrading, repairing, and resting
+    Shop,
+    /// Trading items between players. Listings can still be cancelled while it is paused.
+    Marketplace,
+}
+
+/// The maximum `leaderboard_size` and `season_trophy_count`, because the leaderboards are loaded by every message
+pub const MAX_LEADERBOARD_SIZE: u32 = 100;
+
 /// Coniguration values for the game
 #[derive(Debug, Encode, Decode, SpreadLayout, PackedLayout, SpreadAllocate, Clone)]
 #[cfg_attr(
@@ -65,6 +172,8 @@ pub struct Config {
     pub attack_variance: u32,
     /// Percentage of chance enemy will be wearing a hat
     pub enemy_wearing_hat_chance: u32,
+    /// Defense range of a hat worn by an enemy
+    pub hat_defense_range: Range,
     /// Percentage of chance the hero will go first each round in battle
     pub hero_goes_first_chance: u32,
     /// Cost in gold of resting
@@ -73,6 +182,72 @@ pub struct Config {
     pub potion_cost: TokenBalance,
     /// Cost in gold of a weapon
     pub weapon_cost: TokenBalance,
+    /// Percentage of chance the hero will escape when fleeing a battle
+    pub flee_chance: u32,
+    /// Gold taken from the hero after escaping a battle. If the hero has less, all of it is taken.
+    pub flee_gold_penalty: TokenBalance,
+    /// Health taken from the hero after escaping a battle. It will never reduce health below 1.
+    pub flee_health_penalty: u32,
+    /// Experience awarded for each point of health an enemy starts with
+    pub experience_per_enemy_health: u32,
+    /// Experience awarded for each point of strength an enemy has
+    pub experience_per_enemy_strength: u32,
+    /// The levels a hero can reach. The first entry is level 2, since heroes start at level 1.
+    pub level_growth_table: Vec<LevelGrowth>,
+    /// A boss will be fought each time the hero's consecutive victory count reaches a multiple of this number.
+    /// Bosses are disabled if it's 0.
+    pub boss_victory_threshold: u32,
+    /// A boss's health and strength are multiplied by this number
+    pub boss_stat_multiplier: u32,
+    /// The range of gold a boss drops is multiplied by this number
+    pub boss_gold_multiplier: u32,
+    /// The amount of gold each hero puts up in a duel. The winner receives both.
+    pub duel_wager: TokenBalance,
+    /// The number of blocks a hero has to take their turn in a duel before the other hero can claim a forfeit
+    pub duel_turn_timeout: u32,
+    /// The PvP rating a hero starts with
+    pub pvp_initial_rating: u32,
+    /// The max amount a PvP rating can change after a duel
+    pub pvp_rating_k_factor: u32,
+    /// The max number of heroes kept on the leaderboard, up to `MAX_LEADERBOARD_SIZE`
+    pub leaderboard_size: u32,
+    /// If true, a commitment must be made before starting a battle and a secret must be revealed to start it and
+    /// in each round
+    pub commit_reveal_enabled: bool,
+    /// The number of top finishers on the season leaderboard that receive a trophy when a season ends, up to
+    /// `MAX_LEADERBOARD_SIZE`
+    pub season_trophy_count: u32,
+    /// Percentage of gold spent by heroes that is burned. The rest is kept in the treasury.
+    pub gold_burn_percentage: u32,
+    /// If true, gold dropped by enemies is paid from the treasury. Gold is only minted when the treasury runs out.
+    pub enemy_drops_from_treasury: bool,
+    /// Gold paid for selling a weapon or hat, before its stats are added
+    pub item_sell_base_price: TokenBalance,
+    /// Gold paid for each point of strength and defense of an item that is sold
+    pub item_sell_price_per_point: TokenBalance,
+    /// If true, the part of an item's sell price the treasury can't cover is minted. Otherwise items can't be sold
+    /// while the treasury can't cover the price.
+    pub item_sales_mint_shortfall: bool,
+    /// Percentage of the price of each marketplace sale that is kept in the treasury
+    pub marketplace_fee_percentage: u32,
+    /// Cost in gold of forging two weapons into one
+    pub forge_cost: TokenBalance,
+    /// The strength of a forged weapon starts at this percentage of the combined strength of the two weapons, up
+    /// to 100
+    pub forge_strength_percentage: u32,
+    /// Range of strength added to a forged weapon
+    pub forge_bonus_strength_range: Range,
+    /// The upgrades a weapon can receive. The first entry upgrades a weapon from level 0 to level 1.
+    pub weapon_upgrade_table: Vec<WeaponUpgrade>,
+    /// Durability range of new weapons. Weapons have no durability and never break if it is `None`.
+    pub weapon_durability_range: Option<Range>,
+    /// Cost in gold of restoring each point of durability to a weapon
+    pub repair_cost_per_point: TokenBalance,
+    /// Percentage of its normal damage that a weapon with no durability left deals
+    pub broken_weapon_damage_percentage: u32,
+    /// The rarity tiers new weapons and hats can roll, for common, rare, epic, and legendary items in that order.
+    /// Items with a rarity roll their strength or defense from its tier. Items have no rarity if it is empty.
+    pub rarity_tiers: Vec<RarityTier>,
 }
 
 impl Default for Config {
@@ -87,17 +262,383 @@ impl Default for Config {
             enemy_gold_drop_range: (20, 50).into(),
             attack_variance: 2,
             enemy_wearing_hat_chance: 35,
+            hat_defense_range: (1, 4).into(),
             hero_goes_first_chance: 50,
             rest_cost: 15,
             potion_cost: 50,
             weapon_cost: 125,
+            flee_chance: 50,
+            flee_gold_penalty: 10,
+            flee_health_penalty: 5,
+            experience_per_enemy_health: 1,
+            experience_per_enemy_strength: 2,
+            level_growth_table: (1..10)
+                .map(|level| LevelGrowth {
+                    experience_required: 50 * level * (level + 1),
+                    max_health_increase: 5,
+                })
+                .collect(),
+            boss_victory_threshold: 5,
+            boss_stat_multiplier: 3,
+            boss_gold_multiplier: 5,
+            duel_wager: 50,
+            duel_turn_timeout: 100,
+            pvp_initial_rating: 1200,
+            pvp_rating_k_factor: 32,
+            leaderboard_size: 100,
+            commit_reveal_enabled: false,
+            season_trophy_count: 3,
+            gold_burn_percentage: 100,
+            enemy_drops_from_treasury: false,
+            item_sell_base_price: 10,
+            item_sell_price_per_point: 5,
+            item_sales_mint_shortfall: false,
+            marketplace_fee_percentage: 0,
+            forge_cost: 100,
+            forge_strength_percentage: 60,
+            forge_bonus_strength_range: (0, 3).into(),
+            weapon_upgrade_table: [(50, 0), (100, 0), (200, 10), (400, 25), (800, 40)]
+                .into_iter()
+                .map(|(cost, failure_chance)| WeaponUpgrade {
+                    cost,
+                    strength_increase: 1,
+                    failure_chance,
+                })
+                .collect(),
+            weapon_durability_range: None,
+            repair_cost_per_point: 2,
+            broken_weapon_damage_percentage: 50,
+            rarity_tiers: Vec::new(),
         }
     }
 }
 
-/// Can be used to update config values. See config docs for info on each field.
-#[derive(Debug, Encode, Decode, Default, Clone)]
+/// Returns `Error::InvalidConfig` for `field` if `is_invalid` is true
+fn check(is_invalid: bool, field: ConfigField, reason: InvalidConfigReason) -> Result<()> {
+    if is_invalid {
+        return Err(Error::InvalidConfig { field, reason });
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Returns `Error::InvalidConfig` for the first field with a value that would break the game
+    pub fn validate(&self) -> Result<()> {
+        use ConfigField::*;
+        use InvalidConfigReason::*;
+
+        check(self.hero_max_health == 0, HeroMaxHealth, Zero)?;
+
+        // ranges must not be inverted
+        for (field, range) in [
+            (
+                StartingWeaponStrengthRange,
+                self.starting_weapon_strength_range,
+            ),
+            (
+                PurchasedWeaponStrengthRange,
+                self.purchased_weapon_strength_range,
+            ),
+            (EnemyHealthRange, self.enemy_health_range),
+            (EnemyStrengthRange, self.enemy_strength_range),
+            (EnemyGoldDropRange, self.enemy_gold_drop_range),
+            (HatDefenseRange, self.hat_defense_range),
+            (ForgeBonusStrengthRange, self.forge_bonus_strength_range),
+        ] {
+            check(range.start > range.end, field, InvertedRange)?;
+        }
+        check(self.enemy_health_range.start == 0, EnemyHealthRange, Zero)?;
+        if let Some(range) = self.weapon_durability_range {
+            check(
+                range.start > range.end,
+                WeaponDurabilityRange,
+                InvertedRange,
+            )?;
+            check(range.start == 0, WeaponDurabilityRange, Zero)?;
+        }
+
+        // chances are percentages
+        for (field, chance) in [
+            (EnemyWearingHatChance, self.enemy_wearing_hat_chance),
+            (HeroGoesFirstChance, self.hero_goes_first_chance),
+            (FleeChance, self.flee_chance),
+        ] {
+            check(chance > 100, field, ChanceAbove100)?;
+        }
+        for (field, percentage) in [
+            (GoldBurnPercentage, self.gold_burn_percentage),
+            (MarketplaceFeePercentage, self.marketplace_fee_percentage),
+            (ForgeStrengthPercentage, self.forge_strength_percentage),
+            (
+                BrokenWeaponDamagePercentage,
+                self.broken_weapon_damage_percentage,
+            ),
+        ] {
+            check(percentage > 100, field, PercentageAbove100)?;
+        }
+
+        // each level must require more experience than the one before it
+        check(
+            self.level_growth_table
+                .windows(2)
+                .any(|x| x[0].experience_required >= x[1].experience_required),
+            LevelGrowthTable,
+            NotIncreasing,
+        )?;
+
+        // upgrade failure chances are percentages
+        check(
+            self.weapon_upgrade_table
+                .iter()
+                .any(|upgrade| upgrade.failure_chance > 100),
+            WeaponUpgradeTable,
+            ChanceAbove100,
+        )?;
+
+        // there is at most one tier for each rarity
+        check(
+            self.rarity_tiers.len() > Rarity::COUNT,
+            RarityTiers,
+            TooManyEntries,
+        )?;
+        check(
+            self.rarity_tiers.iter().any(|tier| {
+                tier.strength_range.start > tier.strength_range.end
+                    || tier.defense_range.start > tier.defense_range.end
+            }),
+            RarityTiers,
+            InvertedRange,
+        )?;
+
+        // a multiplier of 0 would spawn bosses that are already dead
+        check(self.boss_stat_multiplier == 0, BossStatMultiplier, Zero)?;
+        check(self.boss_gold_multiplier == 0, BossGoldMultiplier, Zero)?;
+
+        // a timeout of 0 would give a hero no time to take their turn in a duel
+        check(self.duel_turn_timeout == 0, DuelTurnTimeout, Zero)?;
+
+        // the leaderboards must stay small
+        check(
+            self.leaderboard_size > MAX_LEADERBOARD_SIZE,
+            LeaderboardSize,
+            TooManyEntries,
+        )?;
+        check(
+            self.season_trophy_count > MAX_LEADERBOARD_SIZE,
+            SeasonTrophyCount,
+            TooManyEntries,
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns each field with a different value in `other`, along with the value in `self` and in `other`
+    pub fn changes_to(&self, other: &Config) -> Vec<ConfigChange> {
+        let mut changes = Vec::new();
+
+        /// Adds a change if the field is different in `other`
+        macro_rules! compare_field {
+            ($name:ident, $field:ident, $value:ident) => {
+                if self.$name != other.$name {
+                    changes.push(ConfigChange {
+                        field: ConfigField::$field,
+                        old: ConfigValue::$value(self.$name),
+                        new: ConfigValue::$value(other.$name),
+                    });
+                }
+            };
+        }
+
+        // compare each field
+        compare_field!(hero_max_health, HeroMaxHealth, U32);
+        compare_field!(
+            starting_weapon_strength_range,
+            StartingWeaponStrengthRange,
+            Range
+        );
+        compare_field!(
+            purchased_weapon_strength_range,
+            PurchasedWeaponStrengthRange,
+            Range
+        );
+        compare_field!(hero_initial_potion_count, HeroInitialPotionCount, U32);
+        compare_field!(enemy_health_range, EnemyHealthRange, Range);
+        compare_field!(enemy_strength_range, EnemyStrengthRange, Range);
+        compare_field!(enemy_gold_drop_range, EnemyGoldDropRange, Range);
+        compare_field!(attack_variance, AttackVariance, U32);
+        compare_field!(enemy_wearing_hat_chance, EnemyWearingHatChance, U32);
+        compare_field!(hat_defense_range, HatDefenseRange, Range);
+        compare_field!(hero_goes_first_chance, HeroGoesFirstChance, U32);
+        compare_field!(rest_cost, RestCost, Balance);
+        compare_field!(potion_cost, PotionCost, Balance);
+        compare_field!(weapon_cost, WeaponCost, Balance);
+        compare_field!(flee_chance, FleeChance, U32);
+        compare_field!(flee_gold_penalty, FleeGoldPenalty, Balance);
+        compare_field!(flee_health_penalty, FleeHealthPenalty, U32);
+        compare_field!(experience_per_enemy_health, ExperiencePerEnemyHealth, U32);
+        compare_field!(
+            experience_per_enemy_strength,
+            ExperiencePerEnemyStrength,
+            U32
+        );
+        if self.level_growth_table != other.level_growth_table {
+            changes.push(ConfigChange {
+                field: ConfigField::LevelGrowthTable,
+                old: ConfigValue::LevelGrowthTable(self.level_growth_table.clone()),
+                new: ConfigValue::LevelGrowthTable(other.level_growth_table.clone()),
+            });
+        }
+        compare_field!(boss_victory_threshold, BossVictoryThreshold, U32);
+        compare_field!(boss_stat_multiplier, BossStatMultiplier, U32);
+        compare_field!(boss_gold_multiplier, BossGoldMultiplier, U32);
+        compare_field!(duel_wager, DuelWager, Balance);
+        compare_field!(duel_turn_timeout, DuelTurnTimeout, U32);
+        compare_field!(pvp_initial_rating, PvpInitialRating, U32);
+        compare_field!(pvp_rating_k_factor, PvpRatingKFactor, U32);
+        compare_field!(leaderboard_size, LeaderboardSize, U32);
+        compare_field!(commit_reveal_enabled, CommitRevealEnabled, Bool);
+        compare_field!(season_trophy_count, SeasonTrophyCount, U32);
+        compare_field!(gold_burn_percentage, GoldBurnPercentage, U32);
+        compare_field!(enemy_drops_from_treasury, EnemyDropsFromTreasury, Bool);
+        compare_field!(item_sell_base_price, ItemSellBasePrice, Balance);
+        compare_field!(item_sell_price_per_point, ItemSellPricePerPoint, Balance);
+        compare_field!(item_sales_mint_shortfall, ItemSalesMintShortfall, Bool);
+        compare_field!(marketplace_fee_percentage, MarketplaceFeePercentage, U32);
+        compare_field!(forge_cost, ForgeCost, Balance);
+        compare_field!(forge_strength_percentage, ForgeStrengthPercentage, U32);
+        compare_field!(forge_bonus_strength_range, ForgeBonusStrengthRange, Range);
+        if self.weapon_upgrade_table != other.weapon_upgrade_table {
+            changes.push(ConfigChange {
+                field: ConfigField::WeaponUpgradeTable,
+                old: ConfigValue::WeaponUpgradeTable(self.weapon_upgrade_table.clone()),
+                new: ConfigValue::WeaponUpgradeTable(other.weapon_upgrade_table.clone()),
+            });
+        }
+        compare_field!(
+            weapon_durability_range,
+            WeaponDurabilityRange,
+            OptionalRange
+        );
+        compare_field!(repair_cost_per_point, RepairCostPerPoint, Balance);
+        compare_field!(
+            broken_weapon_damage_percentage,
+            BrokenWeaponDamagePercentage,
+            U32
+        );
+        if self.rarity_tiers != other.rarity_tiers {
+            changes.push(ConfigChange {
+                field: ConfigField::RarityTiers,
+                old: ConfigValue::RarityTiers(self.rarity_tiers.clone()),
+                new: ConfigValue::RarityTiers(other.rarity_tiers.clone()),
+            });
+        }
+
+        changes
+    }
+}
+
+/// A change to a single field of `Config`
+#[derive(Debug, Encode, Decode, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo))]
+pub struct ConfigChange {
+    /// The field that changed
+    pub field: ConfigField,
+    /// The value before the change
+    pub old: ConfigValue,
+    /// The value after the change
+    pub new: ConfigValue,
+}
+
+/// The value of a field of `Config`
+#[derive(Debug, Encode, Decode, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo))]
+pub enum ConfigValue {
+    U32(u32),
+    Balance(TokenBalance),
+    Range(Range),
+    OptionalRange(Option<Range>),
+    Bool(bool),
+    LevelGrowthTable(Vec<LevelGrowth>),
+    WeaponUpgradeTable(Vec<WeaponUpgrade>),
+    RarityTiers(Vec<RarityTier>),
+}
+
+/// Identifies a field of `Config`. The enemy fields also identify the matching fields of an `EnemyTemplate`.
+#[derive(Debug, Encode, Decode, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo))]
+pub enum ConfigField {
+    HeroMaxHealth,
+    StartingWeaponStrengthRange,
+    PurchasedWeaponStrengthRange,
+    HeroInitialPotionCount,
+    EnemyHealthRange,
+    EnemyStrengthRange,
+    EnemyGoldDropRange,
+    AttackVariance,
+    EnemyWearingHatChance,
+    HatDefenseRange,
+    HeroGoesFirstChance,
+    RestCost,
+    PotionCost,
+    WeaponCost,
+    FleeChance,
+    FleeGoldPenalty,
+    FleeHealthPenalty,
+    ExperiencePerEnemyHealth,
+    ExperiencePerEnemyStrength,
+    LevelGrowthTable,
+    BossVictoryThreshold,
+    BossStatMultiplier,
+    BossGoldMultiplier,
+    DuelWager,
+    DuelTurnTimeout,
+    PvpInitialRating,
+    PvpRatingKFactor,
+    LeaderboardSize,
+    CommitRevealEnabled,
+    SeasonTrophyCount,
+    GoldBurnPercentage,
+    EnemyDropsFromTreasury,
+    ItemSellBasePrice,
+    ItemSellPricePerPoint,
+    ItemSalesMintShortfall,
+    MarketplaceFeePercentage,
+    ForgeCost,
+    ForgeStrengthPercentage,
+    ForgeBonusStrengthRange,
+    WeaponUpgradeTable,
+    WeaponDurabilityRange,
+    RepairCostPerPoint,
+    BrokenWeaponDamagePercentage,
+    RarityTiers,
+    /// The spawn weight of an `EnemyTemplate`, which has no matching field in `Config`
+    EnemySpawnWeight,
+}
+
+/// The reason a config value is invalid
+#[derive(Debug, Encode, Decode, Copy, Clone, Eq, PartialEq)]
 #[cfg_attr(feature = "std", derive(TypeInfo))]
+pub enum InvalidConfigReason {
+    /// The value must not be 0. For ranges, the start must not be 0.
+    Zero,
+    /// The start of the range is greater than the end
+    InvertedRange,
+    /// The percentage of chance is greater than 100
+    ChanceAbove100,
+    /// The percentage is greater than 100
+    PercentageAbove100,
+    /// The values must be strictly increasing
+    NotIncreasing,
+    /// There are more entries than allowed, or a number of entries is above its maximum
+    TooManyEntries,
+}
+
+/// Can be used to update config values. See config docs for info on each field.
+#[derive(Debug, Encode, Decode, SpreadLayout, PackedLayout, Default, Clone)]
+#[cfg_attr(
+    feature = "std",
+    derive(TypeInfo, ink_storage::traits::StorageLayout, Eq, PartialEq)
+)]
 pub struct ConfigMutation {
     pub hero_max_health: Option<u32>,
     pub starting_weapon_strength_range: Option<Range>,
@@ -107,10 +648,41 @@ pub struct ConfigMutation {
     pub enemy_strength_range: Option<Range>,
     pub enemy_gold_drop_range: Option<Range>,
     pub enemy_wearing_hat_chance: Option<u32>,
+    pub hat_defense_range: Option<Range>,
     pub hero_goes_first_chance: Option<u32>,
     pub rest_cost: Option<TokenBalance>,
     pub potion_cost: Option<TokenBalance>,
     pub weapon_cost: Option<TokenBalance>,
+    pub flee_chance: Option<u32>,
+    pub flee_gold_penalty: Option<TokenBalance>,
+    pub flee_health_penalty: Option<u32>,
+    pub experience_per_enemy_health: Option<u32>,
+    pub experience_per_enemy_strength: Option<u32>,
+    pub level_growth_table: Option<Vec<LevelGrowth>>,
+    pub boss_victory_threshold: Option<u32>,
+    pub boss_stat_multiplier: Option<u32>,
+    pub boss_gold_multiplier: Option<u32>,
+    pub duel_wager: Option<TokenBalance>,
+    pub duel_turn_timeout: Option<u32>,
+    pub pvp_initial_rating: Option<u32>,
+    pub pvp_rating_k_factor: Option<u32>,
+    pub leaderboard_size: Option<u32>,
+    pub commit_reveal_enabled: Option<bool>,
+    pub season_trophy_count: Option<u32>,
+    pub gold_burn_percentage: Option<u32>,
+    pub enemy_drops_from_treasury: Option<bool>,
+    pub item_sell_base_price: Option<TokenBalance>,
+    pub item_sell_price_per_point: Option<TokenBalance>,
+    pub item_sales_mint_shortfall: Option<bool>,
+    pub marketplace_fee_percentage: Option<u32>,
+    pub forge_cost: Option<TokenBalance>,
+    pub forge_strength_percentage: Option<u32>,
+    pub forge_bonus_strength_range: Option<Range>,
+    pub weapon_upgrade_table: Option<Vec<WeaponUpgrade>>,
+    pub weapon_durability_range: Option<Option<Range>>,
+    pub repair_cost_per_point: Option<TokenBalance>,
+    pub broken_weapon_damage_percentage: Option<u32>,
+    pub rarity_tiers: Option<Vec<RarityTier>>,
 }
 
 impl ConfigMutation {
@@ -134,13 +706,178 @@ impl ConfigMutation {
         maybe_set_field!(enemy_strength_range);
         maybe_set_field!(enemy_gold_drop_range);
         maybe_set_field!(enemy_wearing_hat_chance);
+        maybe_set_field!(hat_defense_range);
         maybe_set_field!(hero_goes_first_chance);
         maybe_set_field!(rest_cost);
         maybe_set_field!(potion_cost);
         maybe_set_field!(weapon_cost);
+        maybe_set_field!(flee_chance);
+        maybe_set_field!(flee_gold_penalty);
+        maybe_set_field!(flee_health_penalty);
+        maybe_set_field!(experience_per_enemy_health);
+        maybe_set_field!(experience_per_enemy_strength);
+        maybe_set_field!(level_growth_table);
+        maybe_set_field!(boss_victory_threshold);
+        maybe_set_field!(boss_stat_multiplier);
+        maybe_set_field!(boss_gold_multiplier);
+        maybe_set_field!(duel_wager);
+        maybe_set_field!(duel_turn_timeout);
+        maybe_set_field!(pvp_initial_rating);
+        maybe_set_field!(pvp_rating_k_factor);
+        maybe_set_field!(leaderboard_size);
+        maybe_set_field!(commit_reveal_enabled);
+        maybe_set_field!(season_trophy_count);
+        maybe_set_field!(gold_burn_percentage);
+        maybe_set_field!(enemy_drops_from_treasury);
+        maybe_set_field!(item_sell_base_price);
+        maybe_set_field!(item_sell_price_per_point);
+        maybe_set_field!(item_sales_mint_shortfall);
+        maybe_set_field!(marketplace_fee_percentage);
+        maybe_set_field!(forge_cost);
+        maybe_set_field!(forge_strength_percentage);
+        maybe_set_field!(forge_bonus_strength_range);
+        maybe_set_field!(weapon_upgrade_table);
+        maybe_set_field!(weapon_durability_range);
+        maybe_set_field!(repair_cost_per_point);
+        maybe_set_field!(broken_weapon_damage_percentage);
+        maybe_set_field!(rarity_tiers);
+    }
+
+    /// Returns a mutation that sets each field that is `Some` on `self` back to its value in `config`
+    pub fn revert_from(&self, config: &Config) -> ConfigMutation {
+        /// Takes the value from `config` if the field is `Some` on `self`
+        macro_rules! revert_field {
+            ($name:ident) => {
+                self.$name.as_ref().map(|_| config.$name.clone())
+            };
+        }
+
+        ConfigMutation {
+            hero_max_health: revert_field!(hero_max_health),
+            starting_weapon_strength_range: revert_field!(starting_weapon_strength_range),
+            purchased_weapon_strength_range: revert_field!(purchased_weapon_strength_range),
+            hero_initial_potion_count: revert_field!(hero_initial_potion_count),
+            enemy_health_range: revert_field!(enemy_health_range),
+            enemy_strength_range: revert_field!(enemy_strength_range),
+            enemy_gold_drop_range: revert_field!(enemy_gold_drop_range),
+            enemy_wearing_hat_chance: revert_field!(enemy_wearing_hat_chance),
+            hat_defense_range: revert_field!(hat_defense_range),
+            hero_goes_first_chance: revert_field!(hero_goes_first_chance),
+            rest_cost: revert_field!(rest_cost),
+            potion_cost: revert_field!(potion_cost),
+            weapon_cost: revert_field!(weapon_cost),
+            flee_chance: revert_field!(flee_chance),
+            flee_gold_penalty: revert_field!(flee_gold_penalty),
+            flee_health_penalty: revert_field!(flee_health_penalty),
+            experience_per_enemy_health: revert_field!(experience_per_enemy_health),
+            experience_per_enemy_strength: revert_field!(experience_per_enemy_strength),
+            level_growth_table: revert_field!(level_growth_table),
+            boss_victory_threshold: revert_field!(boss_victory_threshold),
+            boss_stat_multiplier: revert_field!(boss_stat_multiplier),
+            boss_gold_multiplier: revert_field!(boss_gold_multiplier),
+            duel_wager: revert_field!(duel_wager),
+            duel_turn_timeout: revert_field!(duel_turn_timeout),
+            pvp_initial_rating: revert_field!(pvp_initial_rating),
+            pvp_rating_k_factor: revert_field!(pvp_rating_k_factor),
+            leaderboard_size: revert_field!(leaderboard_size),
+            commit_reveal_enabled: revert_field!(commit_reveal_enabled),
+            season_trophy_count: revert_field!(season_trophy_count),
+            gold_burn_percentage: revert_field!(gold_burn_percentage),
+            enemy_drops_from_treasury: revert_field!(enemy_drops_from_treasury),
+            item_sell_base_price: revert_field!(item_sell_base_price),
+            item_sell_price_per_point: revert_field!(item_sell_price_per_point),
+            item_sales_mint_shortfall: revert_field!(item_sales_mint_shortfall),
+            marketplace_fee_percentage: revert_field!(marketplace_fee_percentage),
+            forge_cost: revert_field!(forge_cost),
+            forge_strength_percentage: revert_field!(forge_strength_percentage),
+            forge_bonus_strength_range: revert_field!(forge_bonus_strength_range),
+            weapon_upgrade_table: revert_field!(weapon_upgrade_table),
+            weapon_durability_range: revert_field!(weapon_durability_range),
+            repair_cost_per_point: revert_field!(repair_cost_per_point),
+            broken_weapon_damage_percentage: revert_field!(broken_weapon_damage_percentage),
+            rarity_tiers: revert_field!(rarity_tiers),
+        }
     }
 }
 
+/// The id of a `ConfigSchedule`
+pub type ConfigScheduleId = u32;
+
+/// A `ConfigMutation` that is applied at a future block, and optionally reverted at a later one
+#[derive(Debug, Encode, Decode, SpreadLayout, PackedLayout, Clone)]
+#[cfg_attr(
+    feature = "std",
+    derive(TypeInfo, ink_storage::traits::StorageLayout, Eq, PartialEq)
+)]
+pub struct ConfigSchedule {
+    /// The id of the schedule
+    pub id: ConfigScheduleId,
+    /// The mutation to apply
+    pub mutation: ConfigMutation,
+    /// The block number the mutation is applied at
+    pub activate_at_block: u32,
+    /// The block number the mutation is reverted at. If `None`, the mutation is never reverted.
+    pub revert_at_block: Option<u32>,
+    /// Restores the fields changed by `mutation`. Set when the mutation is applied.
+    pub revert_mutation: Option<ConfigMutation>,
+}
+
+impl ConfigSchedule {
+    /// True if the mutation has been applied
+    pub fn is_active(&self) -> bool {
+        self.revert_mutation.is_some()
+    }
+
+    /// The block the schedule is next due at. This is the revert block once the mutation has been applied.
+    pub fn due_block(&self) -> u32 {
+        match self.revert_at_block {
+            Some(revert_at_block) if self.is_active() => revert_at_block,
+            _ => self.activate_at_block,
+        }
+    }
+}
+
+/// An entry in the level growth table
+#[derive(
+    Debug, Encode, Decode, SpreadLayout, PackedLayout, SpreadAllocate, Copy, Clone, Eq, PartialEq,
+)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct LevelGrowth {
+    /// Total experience required to reach the level
+    pub experience_required: u32,
+    /// Max health gained when reaching the level
+    pub max_health_increase: u32,
+}
+
+/// An entry in the weapon upgrade table
+#[derive(
+    Debug, Encode, Decode, SpreadLayout, PackedLayout, SpreadAllocate, Copy, Clone, Eq, PartialEq,
+)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct WeaponUpgrade {
+    /// Cost in gold of attempting the upgrade
+    pub cost: TokenBalance,
+    /// Strength added to the weapon when the upgrade succeeds
+    pub strength_increase: u32,
+    /// Percentage of chance the upgrade fails. The gold is spent either way.
+    pub failure_chance: u32,
+}
+
+/// An entry in the rarity tiers. Its rarity is determined by its position in the tiers.
+#[derive(
+    Debug, Encode, Decode, SpreadLayout, PackedLayout, SpreadAllocate, Copy, Clone, Eq, PartialEq,
+)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct RarityTier {
+    /// The chance of rolling the tier, relative to the weights of the other tiers
+    pub weight: u32,
+    /// Range of strength of weapons of this rarity, used instead of the range they would otherwise get. Forged
+    /// weapons get their strength from forging instead.
+    pub strength_range: Range,
+    /// Range of defense of hats of this rarity, used instead of `hat_defense_range`
+    pub defense_range: Range,
+}
+
 /// The range is inclusive
 #[derive(
     Debug, Encode, Decode, SpreadLayout, PackedLayout, SpreadAllocate, Copy, Clone, Eq, PartialEq,
@@ -193,11 +930,25 @@ pub struct Hero {
     pub highest_consecutive_victory_count: u32,
     /// The number of battles won in a row, without defeat
     pub consecutive_victory_count: u32,
+    /// Total experience earned
+    pub experience: u32,
+    /// The current level, starting at 1
+    pub level: u32,
+    /// The current duel the hero is engaged in. A challenged hero only joins the duel when accepting it.
+    pub duel_id: Option<DuelId>,
+    /// The Elo rating from duels
+    pub rating: u32,
+    /// The total number of battles won
+    pub victory_count: u32,
+    /// Stats for the last season the hero won a battle in
+    pub season_stats: SeasonStats,
+    /// The commitment the next battle is started with. The secret it reveals decides the enemy.
+    pub commitment: Option<Commitment>,
 }
 
 impl Hero {
     /// Create a new hero
-    pub fn new(health: u32, weapon_id: TokenId, potion_count: u32) -> Self {
+    pub fn new(health: u32, weapon_id: TokenId, potion_count: u32, rating: u32) -> Self {
         Self {
             health,
             weapon_id,
@@ -206,6 +957,13 @@ impl Hero {
             highest_consecutive_victory_count: 0,
             consecutive_victory_count: 0,
             battle: None,
+            experience: 0,
+            level: 1,
+            duel_id: None,
+            rating,
+            victory_count: 0,
+            season_stats: Default::default(),
+            commitment: None,
         }
     }
 
@@ -213,6 +971,77 @@ impl Hero {
     pub fn is_dead(&self) -> bool {
         self.health == 0
     }
+
+    /// Returns true if the hero is in a battle or a duel
+    pub fn is_in_battle(&self) -> bool {
+        self.battle.is_some() || self.duel_id.is_some()
+    }
+
+    /// Returns the hero's stats for `season`. They are all 0 if the stats are from an earlier season.
+    pub fn season_stats(&self, season: u32) -> SeasonStats {
+        if self.season_stats.season == season {
+            self.season_stats
+        } else {
+            SeasonStats {
+                season,
+                ..Default::default()
+            }
+        }
+    }
+
+    /// Updates the lifetime and `season` stats for a battle that was won
+    pub fn record_victory(&mut self, season: u32) {
+        self.victory_count = self.victory_count.saturating_add(1);
+        self.consecutive_victory_count = self.consecutive_victory_count.saturating_add(1);
+        self.highest_consecutive_victory_count = self
+            .highest_consecutive_victory_count
+            .max(self.consecutive_victory_count);
+
+        let mut season_stats = self.season_stats(season);
+        season_stats.victory_count = season_stats.victory_count.saturating_add(1);
+        season_stats.consecutive_victory_count =
+            season_stats.consecutive_victory_count.saturating_add(1);
+        season_stats.highest_consecutive_victory_count = season_stats
+            .highest_consecutive_victory_count
+            .max(season_stats.consecutive_victory_count);
+        self.season_stats = season_stats;
+    }
+
+    /// Ends the lifetime and season streaks of battles won, after a battle was lost
+    pub fn record_defeat(&mut self) {
+        self.consecutive_victory_count = 0;
+        self.season_stats.consecutive_victory_count = 0;
+    }
+}
+
+/// A hero's stats for a single season
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Encode, Decode, SpreadLayout, PackedLayout,
+)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct SeasonStats {
+    /// The season these stats are for
+    pub season: u32,
+    /// The number of battles won this season
+    pub victory_count: u32,
+    /// The number of battles won in a row this season
+    pub consecutive_victory_count: u32,
+    /// The highest number of battles won in a row this season
+    pub highest_consecutive_victory_count: u32,
+}
+
+/// Returned from `get_hero` message. Contains the hero and values derived from the config.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(TypeInfo))]
+pub struct HeroInfo {
+    /// The hero
+    pub hero: Hero,
+    /// Max health of the hero at its current level
+    pub max_health: u32,
+    /// Total experience required to reach the next level. `None` if the hero is at the max level.
+    pub next_level_experience: Option<u32>,
+    /// The hero's stats for the current season. Lifetime stats are on `hero`.
+    pub season_stats: SeasonStats,
 }
 
 /// An action that can be taken in battle
@@ -222,6 +1051,55 @@ pub enum Command {
     Attack,
     /// Recover health to maximum
     Heal,
+    /// Attempt to escape the battle
+    Flee,
+}
+
+/// The id of an `EnemyTemplate`
+pub type EnemyTypeId = u32;
+
+/// A template in the bestiary used to generate enemies
+#[derive(Debug, Encode, Decode, SpreadLayout, PackedLayout, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct EnemyTemplate {
+    /// The id of the template
+    pub id: EnemyTypeId,
+    /// The name of the enemy
+    pub name: Vec<u8>,
+    /// Health range of the enemy
+    pub health_range: Range,
+    /// Strength range of the enemy
+    pub strength_range: Range,
+    /// Range of amount of gold the enemy drops
+    pub gold_drop_range: Range,
+    /// Percentage of chance the enemy will be wearing a hat
+    pub hat_chance: u32,
+    /// The weight of this template when randomly selecting an enemy. Higher weights spawn more often.
+    pub spawn_weight: u32,
+    /// Only enabled templates will spawn
+    pub enabled: bool,
+}
+
+impl EnemyTemplate {
+    /// Returns `Error::InvalidConfig` for the first field with a value that would break the game. Fields are
+    /// identified by the `Config` field they replace.
+    pub fn validate(&self) -> Result<()> {
+        use ConfigField::*;
+        use InvalidConfigReason::*;
+
+        for (field, range) in [
+            (EnemyHealthRange, self.health_range),
+            (EnemyStrengthRange, self.strength_range),
+            (EnemyGoldDropRange, self.gold_drop_range),
+        ] {
+            check(range.start > range.end, field, InvertedRange)?;
+        }
+        check(self.health_range.start == 0, EnemyHealthRange, Zero)?;
+        check(self.hat_chance > 100, EnemyWearingHatChance, ChanceAbove100)?;
+        check(self.spawn_weight == 0, EnemySpawnWeight, Zero)?;
+
+        Ok(())
+    }
 }
 
 /// An entity that can be fought
@@ -236,6 +1114,14 @@ pub struct Enemy {
     pub health: u32,
     /// Determines the power of a delivered attack
     pub strength: u32,
+    /// Experience awarded to the hero for defeating this enemy
+    pub experience: u32,
+    /// Range of amount of gold the enemy drops
+    pub gold_drop_range: Range,
+    /// The id of the template the enemy was generated from. `None` if it was generated from the config.
+    pub template_id: Option<EnemyTypeId>,
+    /// True if the enemy is a boss
+    pub is_boss: bool,
 }
 
 impl Enemy {
@@ -255,20 +1141,119 @@ pub struct Battle {
     pub round_number: u32,
     /// The enemy involved in this battle
     pub enemy: Enemy,
+    /// True if the hero escaped from this battle
+    pub hero_fled: bool,
+    /// The commitment the next round's secret must match. `None` if the battle does not use commit-reveal.
+    pub commitment: Option<Commitment>,
 }
 
 impl Battle {
     /// Create a new battle
-    pub fn new(enemy: Enemy) -> Self {
+    pub fn new(enemy: Enemy, commitment: Option<Commitment>) -> Self {
         Self {
             round_number: 0,
             enemy,
+            hero_fled: false,
+            commitment,
         }
     }
 }
 
+/// A hash a hero committed to, which a secret must be revealed for
+#[derive(
+    Debug, Encode, Decode, SpreadLayout, PackedLayout, SpreadAllocate, Copy, Clone, Eq, PartialEq,
+)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct Commitment {
+    /// The hash the revealed secret must match
+    pub hash: [u8; 32],
+    /// The block the commitment was recorded in. The secret can only be revealed in a later block, and is mixed
+    /// with the chain randomness of the block it's revealed in.
+    pub block_number: u32,
+}
+
+/// The id of a `Duel`
+pub type DuelId = u32;
+
+/// A battle between two heroes
+#[derive(Debug, Encode, Decode, SpreadLayout, PackedLayout, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct Duel {
+    /// The hero that issued the challenge
+    pub challenger: AccountId,
+    /// The hero that was challenged
+    pub opponent: AccountId,
+    /// The amount of gold each hero holds in escrow
+    pub wager: TokenBalance,
+    /// True once the opponent accepted the challenge
+    pub accepted: bool,
+    /// The hero whose turn it is
+    pub turn: AccountId,
+    /// The number of turns taken in this duel
+    pub turn_number: u32,
+    /// The block number after which the hero whose turn it is forfeits. Only set once the duel is accepted.
+    pub turn_deadline: Option<u32>,
+}
+
+impl Duel {
+    /// Create a new duel that has not been accepted yet
+    pub fn new(challenger: AccountId, opponent: AccountId, wager: TokenBalance) -> Self {
+        Self {
+            challenger,
+            opponent,
+            wager,
+            accepted: false,
+            turn: challenger,
+            turn_number: 0,
+            turn_deadline: None,
+        }
+    }
+
+    /// Returns the participant that is not `hero_id`
+    pub fn other(&self, hero_id: AccountId) -> AccountId {
+        if hero_id == self.challenger {
+            self.opponent
+        } else {
+            self.challenger
+        }
+    }
+}
+
+/// A hero's position on the leaderboard
+#[derive(Debug, Encode, Decode, SpreadLayout, PackedLayout, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct LeaderboardEntry {
+    /// The `AccountId` of the hero
+    pub hero_id: AccountId,
+    /// The rating of the hero
+    pub rating: u32,
+}
+
+/// A hero's position on the season leaderboard
+#[derive(Debug, Encode, Decode, SpreadLayout, PackedLayout, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct SeasonLeaderboardEntry {
+    /// The `AccountId` of the hero
+    pub hero_id: AccountId,
+    /// The number of battles the hero won this season
+    pub victory_count: u32,
+}
+
 // Tokens
 
+/// An item for sale on the marketplace. The item is held by the contract until it's sold or the listing is
+/// cancelled.
+#[derive(Debug, Encode, Decode, SpreadLayout, PackedLayout, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo, ink_storage::traits::StorageLayout))]
+pub struct Listing {
+    /// The account selling the item
+    pub seller: AccountId,
+    /// The `TokenId` of the item
+    pub token_id: TokenId,
+    /// The price in gold
+    pub price: TokenBalance,
+}
+
 /// A type that a token can be
 #[derive(Encode, Decode, Eq, PartialEq, Copy, Clone, Debug)]
 #[cfg_attr(feature = "std", derive(TypeInfo))]
@@ -279,6 +1264,10 @@ pub enum TokenType {
     Weapon = 1,
     /// The token is a hat
     Hat = 2,
+    /// The token is a trophy for defeating a boss
+    Trophy = 3,
+    /// The token is a trophy for finishing at the top of a season
+    SeasonTrophy = 4,
 }
 
 impl TokenType {
@@ -286,16 +1275,62 @@ impl TokenType {
     pub fn from_value(value: u8) -> Option<Self> {
         const WEAPON_VALUE: u8 = TokenType::Weapon as _;
         const HAT_VALUE: u8 = TokenType::Hat as _;
+        const TROPHY_VALUE: u8 = TokenType::Trophy as _;
+        const SEASON_TROPHY_VALUE: u8 = TokenType::SeasonTrophy as _;
 
         match value {
             WEAPON_VALUE => Some(Self::Weapon),
             HAT_VALUE => Some(Self::Hat),
+            TROPHY_VALUE => Some(Self::Trophy),
+            SEASON_TROPHY_VALUE => Some(Self::SeasonTrophy),
+            _ => None,
+        }
+    }
+}
+
+/// How rare a weapon or hat is. Rarer items get bigger bonuses to their stats.
+#[derive(Encode, Decode, Eq, PartialEq, Copy, Clone, Debug)]
+#[cfg_attr(feature = "std", derive(TypeInfo))]
+#[allow(clippy::unnecessary_cast)]
+#[repr(u8)]
+pub enum Rarity {
+    /// The most frequent rarity
+    Common = 1,
+    /// Less frequent than common
+    Rare = 2,
+    /// Less frequent than rare
+    Epic = 3,
+    /// The least frequent rarity
+    Legendary = 4,
+}
+
+impl Rarity {
+    /// The number of rarities
+    pub const COUNT: usize = 4;
+
+    /// Create from a u8
+    pub fn from_value(value: u8) -> Option<Self> {
+        const COMMON_VALUE: u8 = Rarity::Common as _;
+        const RARE_VALUE: u8 = Rarity::Rare as _;
+        const EPIC_VALUE: u8 = Rarity::Epic as _;
+        const LEGENDARY_VALUE: u8 = Rarity::Legendary as _;
+
+        match value {
+            COMMON_VALUE => Some(Self::Common),
+            RARE_VALUE => Some(Self::Rare),
+            EPIC_VALUE => Some(Self::Epic),
+            LEGENDARY_VALUE => Some(Self::Legendary),
             _ => None,
         }
     }
+
+    /// The index of the rarity's tier in `Config::rarity_tiers`
+    pub fn tier_index(&self) -> usize {
+        *self as usize - 1
+    }
 }
 
-/// Wraps the `TokenId` so we can encode the `TokenType` in it
+/// Wraps the `TokenId` so we can encode the `TokenType` and `Rarity` in it
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub(crate) struct WrappedTokenId(pub TokenId);
 
@@ -304,6 +1339,10 @@ impl WrappedTokenId {
     const TOKEN_TYPE_INDEX: u128 = 124;
     /// The bit-mask of the token type
     const TOKEN_TYPE_BIT_MASK: u128 = 0b1111 << Self::TOKEN_TYPE_INDEX;
+    /// The bit index to store the rarity
+    const RARITY_INDEX: u128 = 120;
+    /// The bit-mask of the rarity
+    const RARITY_BIT_MASK: u128 = 0b1111 << Self::RARITY_INDEX;
 
     /// Create a new instance
     pub fn new(id: TokenId, token_type: Option<TokenType>) -> Self {
@@ -329,18 +1368,92 @@ impl WrappedTokenId {
         TokenType::from_value(value as u8)
     }
 
+    /// Set the rarity
+    pub fn set_rarity(&mut self, rarity: Option<Rarity>) {
+        let rarity_value: u128 = rarity.map(|x| x as u8).unwrap_or_default().into();
+
+        // clear bits
+        self.0 &= !Self::RARITY_BIT_MASK;
+
+        // set mask
+        self.0 |= rarity_value << Self::RARITY_INDEX;
+    }
+
+    /// Get the rarity
+    pub fn rarity(&self) -> Option<Rarity> {
+        let value = (self.0 & Self::RARITY_BIT_MASK) >> Self::RARITY_INDEX;
+        Rarity::from_value(value as u8)
+    }
+
     /// The id without any encoding
     pub fn id(&self) -> TokenId {
-        self.0 & !Self::TOKEN_TYPE_BIT_MASK
+        self.0 & !Self::TOKEN_TYPE_BIT_MASK & !Self::RARITY_BIT_MASK
     }
 }
 
 /// Metadata stored for the token as an attribute
-#[derive(Encode, Decode)]
+#[derive(Encode, Decode, Default)]
 #[cfg_attr(feature = "std", derive(TypeInfo))]
 pub struct TokenMetadata {
     /// The strength value
     pub strength: u32,
+    /// The defense value. Subtracted from the damage the wearer receives.
+    pub defense: u32,
+    /// The number of upgrades applied to a weapon
+    pub upgrade_level: u32,
+    /// The durability of a weapon. `None` if the weapon never breaks.
+    pub durability: Option<Durability>,
+    /// The rarity of a weapon or hat. Also encoded in its `TokenId`.
+    pub rarity: Option<Rarity>,
+}
+
+impl TokenMetadata {
+    /// The version stored in front of the encoded metadata. Increment it when the layout changes, and keep
+    /// decoding the previous layouts in `decode_attribute`.
+    pub const VERSION: u8 = 1;
+
+    /// Encodes the metadata to be stored as an attribute, prefixed with `VERSION`
+    pub fn encode_attribute(&self) -> Vec<u8> {
+        (Self::VERSION, self).encode()
+    }
+
+    /// Decodes metadata stored as an attribute. Returns `None` if the layout is unknown.
+    pub fn decode_attribute(value: &[u8]) -> Option<Self> {
+        // metadata stored before it was versioned only has the strength. Versioned metadata is never this short.
+        if value.len() == 4 {
+            return Some(Self {
+                strength: Decode::decode(&mut &value[..]).ok()?,
+                ..Default::default()
+            });
+        }
+
+        match value.split_first() {
+            Some((&Self::VERSION, mut layout)) => Decode::decode(&mut layout).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// The durability of a weapon. It's reduced by 1 each time the weapon attacks in battle.
+#[derive(Encode, Decode, Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(TypeInfo))]
+pub struct Durability {
+    /// The durability left
+    pub current: u32,
+    /// The durability the weapon is restored to when repaired
+    pub max: u32,
+}
+
+impl Durability {
+    /// Create a new durability that is fully repaired
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    /// Returns true if there is no durability left
+    pub fn is_broken(&self) -> bool {
+        self.current == 0
+    }
 }
 
 /// Returned from `get_token_info` message. Contains info about a token id.
@@ -349,6 +1462,8 @@ pub struct TokenMetadata {
 pub struct TokenIdInfo {
     /// The type of the token
     pub token_type: Option<TokenType>,
+    /// The rarity of the token
+    pub rarity: Option<Rarity>,
     /// The id of the token
     pub id: TokenId,
 }